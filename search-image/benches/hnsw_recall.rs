@@ -0,0 +1,108 @@
+//! Recall@k of [`HnswIndex`] against an exact scan over the same vectors.
+//!
+//! Run with `cargo bench -p search-image --bench hnsw_recall`. The data is
+//! synthetic: 960-dim vectors (the MobileNetV4 feature size) drawn around a
+//! set of cluster centres, which is closer to real image embeddings than
+//! uniform noise.
+
+use search_image::{config::HnswConfig, store::HnswIndex};
+use std::time::Instant;
+
+const DIM: usize = 960;
+const POINTS: usize = 20_000;
+const CLUSTERS: usize = 100;
+const QUERIES: usize = 200;
+const K: usize = 10;
+
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+    }
+}
+
+fn normalize(v: &[f32]) -> Vec<f32> {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    v.iter().map(|x| x / norm).collect()
+}
+
+fn dataset(rng: &mut XorShift, centres: &[Vec<f32>], n: usize) -> Vec<Vec<f32>> {
+    (0..n)
+        .map(|i| {
+            let centre = &centres[i % centres.len()];
+            centre.iter().map(|c| c + 0.5 * rng.next()).collect()
+        })
+        .collect()
+}
+
+fn exact(data: &[Vec<f32>], query: &[f32], k: usize) -> Vec<u32> {
+    let query = normalize(query);
+    let mut scored = data
+        .iter()
+        .enumerate()
+        .map(|(i, v)| (v.iter().zip(&query).map(|(a, b)| a * b).sum::<f32>(), i))
+        .collect::<Vec<_>>();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.into_iter().take(k).map(|(_, i)| i as u32).collect()
+}
+
+fn main() {
+    let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+    let centres = (0..CLUSTERS)
+        .map(|_| (0..DIM).map(|_| rng.next()).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    // The index reads unit-length vectors from their owner.
+    let data = dataset(&mut rng, &centres, POINTS)
+        .iter()
+        .map(|v| normalize(v))
+        .collect::<Vec<_>>();
+    let queries = dataset(&mut rng, &centres, QUERIES);
+
+    let config = HnswConfig::new(16, 200, 64);
+    let start = Instant::now();
+    let mut index = HnswIndex::new(DIM, config);
+    for _ in &data {
+        index.insert(&data[..]).unwrap();
+    }
+    println!(
+        "built {POINTS} x {DIM} (m = {}, ef_construction = {}) in {:.2?}",
+        config.m(),
+        config.ef_construction(),
+        start.elapsed()
+    );
+
+    let start = Instant::now();
+    let truth = queries
+        .iter()
+        .map(|q| exact(&data, q, K))
+        .collect::<Vec<_>>();
+    let exact_time = start.elapsed();
+    println!(
+        "exact: {:.1} queries/s",
+        QUERIES as f64 / exact_time.as_secs_f64()
+    );
+
+    for ef_search in [16, 32, 64, 128, 256] {
+        index.set_ef_search(ef_search);
+        let start = Instant::now();
+        let results = queries
+            .iter()
+            .map(|q| index.search(&data[..], q, K))
+            .collect::<Vec<_>>();
+        let elapsed = start.elapsed();
+        let found = results
+            .iter()
+            .zip(&truth)
+            .map(|(hits, truth)| hits.iter().filter(|(node, _)| truth.contains(node)).count())
+            .sum::<usize>();
+        println!(
+            "ef_search = {ef_search:>3}: recall@{K} = {:.4}, {:.1} queries/s",
+            found as f64 / (QUERIES * K) as f64,
+            QUERIES as f64 / elapsed.as_secs_f64()
+        );
+    }
+}
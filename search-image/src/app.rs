@@ -1,20 +1,26 @@
 use crate::{
-    config::{DbConfig, MobilenetConfig},
-    database,
-    error::{Error, Result},
-    extractor::{Extractor, FEATURE_SIZE},
-};
-use qdrant_client::{
-    Qdrant, QdrantBuilder,
-    config::CompressionEncoding,
-    qdrant::{CreateCollectionBuilder, Distance, VectorParamsBuilder},
+    config::{DbConfig, IdStrategy, MobilenetConfig},
+    dedup::{Duplicate, DuplicateReport, ImageHash, cluster},
+    error::{Error, ItemError, ItemResult, Result},
+    extractor::{Chunk, Extractor, HashedFeature},
+    filter::{Condition, PayloadFilter, path_dirs},
+    ingest::{DuplicateCheck, DuplicateMatch, DuplicatePolicy, IngestError, IngestReport, Skipped},
+    scan::FolderScan,
+    schema::{self, CollectionMeta},
+    store::{ScrollPage, SearchOptions, Store, VectorStore},
+    sync::{Change, Manifest, ManifestEntry, SyncReport, detect_change, manifest_key},
+    utils::{content_hash, content_id, load_image, path_id},
 };
+use image::DynamicImage;
+use qdrant_client::{Qdrant, qdrant::ScoredPoint};
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::collections::{HashMap, HashSet};
 
-pub struct App {
-    db: Qdrant,
+pub struct App<S = Store> {
+    store: S,
     extractor: Extractor,
-    collection: String,
+    ids: IdStrategy,
+    duplicates: Option<DuplicateCheck>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,8 +28,21 @@ pub struct ImageInfo<T = ()> {
     #[serde(skip)]
     id: String,
     path: String,
+    /// Directories `path` lies in, matched by [`Condition::PathPrefix`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    dirs: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     extra: Option<T>,
+    /// [`Preprocessor::version`](crate::preprocess::Preprocessor::version) of
+    /// the pipeline that produced the stored vector.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    preprocess: Option<String>,
+    /// Perceptual hash of the image, used for duplicate detection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<ImageHash>,
+    /// Id of the point this image was found to duplicate on ingestion.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    duplicate_of: Option<String>,
 }
 
 impl<T> ImageInfo<T> {
@@ -31,7 +50,11 @@ impl<T> ImageInfo<T> {
         Self {
             id: uuid::Uuid::new_v4().to_string(),
             path: path.to_string(),
+            dirs: path_dirs(path),
             extra: Some(extra),
+            preprocess: None,
+            hash: None,
+            duplicate_of: None,
         }
     }
 
@@ -39,7 +62,107 @@ impl<T> ImageInfo<T> {
         Self {
             id: uuid::Uuid::new_v4().to_string(),
             path: path.to_string(),
+            dirs: path_dirs(path),
             extra: None,
+            preprocess: None,
+            hash: None,
+            duplicate_of: None,
+        }
+    }
+
+    pub fn new(id: &str, path: &str, extra: Option<T>) -> Self {
+        Self {
+            id: id.to_string(),
+            path: path.to_string(),
+            dirs: path_dirs(path),
+            extra,
+            preprocess: None,
+            hash: None,
+            duplicate_of: None,
+        }
+    }
+
+    /// Recomputes the directories of `path`, which payloads stored before
+    /// they were recorded lack.
+    pub(crate) fn with_dirs(mut self) -> Self {
+        self.dirs = path_dirs(&self.path);
+        self
+    }
+
+    pub fn with_preprocess(mut self, version: &str) -> Self {
+        self.preprocess = Some(version.to_string());
+        self
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn extra(&self) -> Option<&T> {
+        self.extra.as_ref()
+    }
+
+    pub fn preprocess(&self) -> Option<&str> {
+        self.preprocess.as_deref()
+    }
+
+    pub fn with_hash(mut self, hash: ImageHash) -> Self {
+        self.hash = Some(hash);
+        self
+    }
+
+    pub fn hash(&self) -> Option<&ImageHash> {
+        self.hash.as_ref()
+    }
+
+    pub fn duplicate_of(&self) -> Option<&str> {
+        self.duplicate_of.as_deref()
+    }
+}
+
+impl<T: DeserializeOwned> ImageInfo<T> {
+    /// Rebuilds an `ImageInfo` from a stored payload. The id is not part of
+    /// the payload, it comes from the point that carried it.
+    pub fn from_payload(id: String, payload: serde_json::Value) -> Result<Self> {
+        let mut info: Self = serde_json::from_value(payload)
+            .map_err(|e| Error::PayloadError(format!("point {id}: {e}")))?;
+        info.id = id;
+        Ok(info)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit<T = ()> {
+    id: String,
+    score: f32,
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extra: Option<T>,
+    #[serde(skip)]
+    preprocess: Option<String>,
+}
+
+impl<T: DeserializeOwned> TryFrom<ScoredPoint> for SearchHit<T> {
+    type Error = Error;
+
+    fn try_from(point: ScoredPoint) -> Result<Self> {
+        let score = point.score;
+        Ok(Self::new(ImageInfo::try_from(point)?, score))
+    }
+}
+
+impl<T> SearchHit<T> {
+    pub fn new(info: ImageInfo<T>, score: f32) -> Self {
+        Self {
+            id: info.id,
+            score,
+            path: info.path,
+            extra: info.extra,
+            preprocess: info.preprocess,
         }
     }
 
@@ -47,6 +170,10 @@ impl<T> ImageInfo<T> {
         &self.id
     }
 
+    pub fn score(&self) -> f32 {
+        self.score
+    }
+
     pub fn path(&self) -> &str {
         &self.path
     }
@@ -54,79 +181,1084 @@ impl<T> ImageInfo<T> {
     pub fn extra(&self) -> Option<&T> {
         self.extra.as_ref()
     }
+
+    pub fn preprocess(&self) -> Option<&str> {
+        self.preprocess.as_deref()
+    }
+
+    pub fn into_extra(self) -> Option<T> {
+        self.extra
+    }
 }
 
-impl App {
+impl App<Store> {
+    /// Opens the configured store and loads the model. Fails with
+    /// [`Error::SchemaMismatch`] if the collection holds vectors from another
+    /// network or preprocessing pipeline, and with
+    /// [`Error::UnrecordedCollection`] if it holds vectors of an unknown one.
     pub async fn new(db_config: &DbConfig, mobilenet_config: &MobilenetConfig) -> Result<Self> {
         let device = mobilenet_config.device().into_device()?;
-        let extractor = Extractor::new(mobilenet_config.kind(), &device).await?;
-        let qdrant_url = format!("http://{}:{}", db_config.url(), db_config.port());
-        let db = QdrantBuilder::from_url(&qdrant_url)
-            .connect_timeout(std::time::Duration::from_secs(30))
-            .timeout(std::time::Duration::from_secs(30))
-            .compression(Some(CompressionEncoding::Gzip))
-            .keep_alive_while_idle()
-            .build()
-            .map_err(|e| Error::QdrantBuildError(e.to_string()))?;
-
-        let collection = db_config.collection().to_string();
-
-        if !db
-            .collection_exists(&collection)
-            .await
-            .map_err(|e| Error::CollectionError(e.to_string()))?
-        {
-            db.create_collection(CreateCollectionBuilder::new(&collection).vectors_config(
-                VectorParamsBuilder::new(FEATURE_SIZE as u64, Distance::Cosine),
-            ))
-            .await
-            .map_err(|e| Error::CollectionError(e.to_string()))?;
-        }
+        let extractor = Extractor::with_config(mobilenet_config, &device).await?;
+        let model = extractor.model().name();
+        let store = Store::open(db_config, extractor.feature_size(), &model).await?;
+        schema::verify(
+            &store,
+            db_config.collection(),
+            &CollectionMeta {
+                metric: db_config.params().distance(),
+                ..CollectionMeta::for_extractor(&extractor)
+            },
+        )
+        .await?;
+        Ok(Self::with_store(store, extractor).with_id_strategy(db_config.ids()))
+    }
+
+    /// The Qdrant client, if the app is backed by Qdrant.
+    pub fn qdrant(&self) -> Option<&Qdrant> {
+        self.store.as_qdrant().map(|store| store.client())
+    }
+
+    /// # Panics
+    ///
+    /// If the app is not backed by Qdrant.
+    #[deprecated(note = "use `qdrant`, which also covers the other backends")]
+    pub fn db(&self) -> &Qdrant {
+        self.qdrant().expect("the app is not backed by Qdrant")
+    }
+
+    /// # Panics
+    ///
+    /// If the app is not backed by Qdrant.
+    #[deprecated(note = "use `store().as_qdrant()` and `QdrantStore::collection`")]
+    pub fn collection(&self) -> &str {
+        self.store
+            .as_qdrant()
+            .expect("the app is not backed by Qdrant")
+            .collection()
+    }
+}
 
-        Ok(Self {
-            db,
+impl<S: VectorStore> App<S> {
+    pub fn with_store(store: S, extractor: Extractor) -> Self {
+        Self {
+            store,
             extractor,
-            collection,
+            ids: IdStrategy::default(),
+            duplicates: None,
+        }
+    }
+
+    /// Checks every image ingested through [`add_images`](Self::add_images)
+    /// and its siblings against the collection, applying `check`'s policy
+    /// to the ones that are too similar to a stored point.
+    pub fn with_duplicate_check(mut self, check: DuplicateCheck) -> Self {
+        self.duplicates = Some(check);
+        self
+    }
+
+    pub fn duplicate_check(&self) -> Option<&DuplicateCheck> {
+        self.duplicates.as_ref()
+    }
+
+    pub fn with_id_strategy(mut self, ids: IdStrategy) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    pub fn id_strategy(&self) -> IdStrategy {
+        self.ids
+    }
+
+    /// The id the file at `path` is stored under with the current
+    /// [`IdStrategy`]. With [`IdStrategy::Content`], an existing point with
+    /// this id means an identical file was already indexed.
+    pub fn point_id(&self, path: impl AsRef<std::path::Path>) -> Result<String> {
+        Ok(match self.ids {
+            IdStrategy::Random => uuid::Uuid::new_v4().to_string(),
+            IdStrategy::Content => content_id(&content_hash(path)?),
+            IdStrategy::Path => path_id(path),
         })
     }
 
-    pub fn db(&self) -> &Qdrant {
-        &self.db
+    pub fn store(&self) -> &S {
+        &self.store
     }
 
     pub fn extractor(&self) -> &Extractor {
         &self.extractor
     }
 
-    pub fn collection(&self) -> &str {
-        &self.collection
+    fn preprocess_version(&self) -> String {
+        self.extractor.preprocessor().version()
+    }
+
+    /// Indexes the images at `paths`. Files that cannot be read or decoded
+    /// are skipped and listed in the report, the rest are stored. When
+    /// storing fails, the error carries the report of the chunks stored
+    /// until then.
+    pub async fn add_images<T: AsRef<std::path::Path> + Sync>(
+        &self,
+        paths: &[T],
+    ) -> std::result::Result<IngestReport, IngestError> {
+        let mut skipped = Vec::new();
+        let (paths, info) = self.assign_ids(paths, std::iter::repeat(None), &mut skipped)?;
+        let mut result = self.ingest(&paths, info).await;
+        match &mut result {
+            Ok(report) | Err(IngestError { report, .. }) => report.skipped.splice(0..0, skipped),
+        };
+        result
     }
 
-    pub async fn add_images<T: AsRef<std::path::Path>>(&self, paths: &[T]) -> Result<()> {
-        let info = paths
+    /// Indexes every image under `folder` selected by `scan` and returns the
+    /// `ImageInfo`s it created along with the files it skipped.
+    pub async fn index_folder(
+        &self,
+        folder: impl AsRef<std::path::Path>,
+        scan: &FolderScan,
+    ) -> std::result::Result<IngestReport, IngestError> {
+        let paths = scan.scan(folder)?;
+        self.add_images(&paths).await
+    }
+
+    /// Brings the index in line with `folder`, using the manifest at
+    /// `manifest_path` to find what changed since the last sync. New files are
+    /// added, modified files are re-embedded and files that disappeared are
+    /// deleted. Files are only hashed when their size or modification time
+    /// moved. A point shared by identical files under
+    /// [`IdStrategy::Content`] is kept while any of them remains. If storing
+    /// fails part way, the manifest is still saved with the files stored
+    /// before the error.
+    pub async fn sync_folder(
+        &self,
+        folder: impl AsRef<std::path::Path>,
+        scan: &FolderScan,
+        manifest_path: impl AsRef<std::path::Path>,
+    ) -> Result<SyncReport> {
+        let mut manifest = Manifest::load(&manifest_path)?;
+        let paths = scan.scan(folder)?;
+        let present = paths
             .iter()
-            .map(|path| ImageInfo::with_path(&path.as_ref().to_string_lossy()))
-            .collect::<Vec<ImageInfo<()>>>();
-        let features = self.extractor().extract_batch(paths)?;
-        database::add(&self.db, &self.collection, &features, &info).await
+            .map(|path| manifest_key(path))
+            .collect::<HashSet<_>>();
+        let gone = manifest
+            .paths()
+            .filter(|path| !present.contains(*path))
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        let result = self.apply_sync(&mut manifest, &paths, &gone).await;
+        manifest.save(manifest_path)?;
+        result
+    }
+
+    /// Like [`sync_folder`](Self::sync_folder), restricted to `changed` paths
+    /// below `root`, typically taken from filesystem events. A path may be a
+    /// file or a directory; whatever no longer exists under it is removed.
+    pub async fn sync_paths<P: AsRef<std::path::Path>>(
+        &self,
+        root: impl AsRef<std::path::Path>,
+        scan: &FolderScan,
+        changed: &[P],
+        manifest_path: impl AsRef<std::path::Path>,
+    ) -> Result<SyncReport> {
+        let root = root.as_ref();
+        let mut manifest = Manifest::load(&manifest_path)?;
+        let filter = scan.filter(root)?;
+        let mut present = Vec::new();
+        for path in changed {
+            let path = path.as_ref();
+            if path.is_dir() {
+                present.extend(
+                    scan.scan(path)?
+                        .into_iter()
+                        .filter(|file| filter.accepts(file)),
+                );
+            } else if path.is_file() && filter.accepts(path) {
+                present.push(path.to_path_buf());
+            }
+        }
+        present.sort();
+        present.dedup();
+        let kept = present
+            .iter()
+            .map(|path| manifest_key(path))
+            .collect::<HashSet<_>>();
+        let gone = manifest
+            .paths()
+            .filter(|key| !kept.contains(*key))
+            .filter(|key| {
+                let key = std::path::Path::new(key);
+                changed.iter().any(|path| key.starts_with(path))
+            })
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        let result = self.apply_sync(&mut manifest, &present, &gone).await;
+        manifest.save(manifest_path)?;
+        result
+    }
+
+    /// Re-embeds the `present` files that changed according to `manifest` and
+    /// deletes the points of the `gone` manifest entries. When storing fails
+    /// part way, `manifest` still takes in the files stored before the error.
+    async fn apply_sync(
+        &self,
+        manifest: &mut Manifest,
+        paths: &[std::path::PathBuf],
+        gone: &[String],
+    ) -> Result<SyncReport> {
+        let mut report = SyncReport::default();
+
+        let mut pending = Vec::new();
+        let mut info = Vec::new();
+        let mut stamps = HashMap::new();
+        // Points owned by the modified files, given up once the new content
+        // is recorded.
+        let mut previous = HashMap::new();
+        for path in paths {
+            let key = manifest_key(path);
+            let change = match detect_change(manifest, path) {
+                Ok(change) => change,
+                Err(e) => {
+                    report.skipped.push(Skipped {
+                        path: key,
+                        error: ItemError::classify(e)?,
+                    });
+                    continue;
+                }
+            };
+            let (id, size, mtime, hash) = match change {
+                Change::Unchanged => {
+                    report.unchanged += 1;
+                    continue;
+                }
+                Change::Touched(entry) => {
+                    report.unchanged += 1;
+                    manifest.insert(key, entry);
+                    continue;
+                }
+                Change::Added { size, mtime, hash } => {
+                    (self.synced_id(path, &hash, None), size, mtime, hash)
+                }
+                Change::Modified {
+                    id,
+                    size,
+                    mtime,
+                    hash,
+                } => {
+                    previous.insert(key.clone(), id.clone());
+                    (self.synced_id(path, &hash, Some(id)), size, mtime, hash)
+                }
+            };
+            info.push(ImageInfo::<()>::new(&id, &key, None));
+            stamps.insert(key, (size, mtime, hash));
+            pending.push(path);
+        }
+
+        // Record what was stored even if a later chunk failed, so that it is
+        // not embedded again.
+        let (ingested, failure) = match self.ingest(&pending, info).await {
+            Ok(ingested) => (ingested, None),
+            Err(IngestError { report, error }) => (report, Some(error)),
+        };
+        let mut orphans = Vec::new();
+        for added in &ingested.added {
+            if let Some((size, mtime, hash)) = stamps.remove(added.path()) {
+                // A duplicate check with the replace policy may have moved
+                // the image onto an existing point.
+                let entry = ManifestEntry {
+                    id: added.id().to_string(),
+                    size,
+                    mtime,
+                    hash,
+                    duplicate: false,
+                };
+                match manifest.get(added.path()) {
+                    Some(_) => report.updated += 1,
+                    None => report.added += 1,
+                }
+                orphans.extend(previous.remove(added.path()).filter(|id| id != added.id()));
+                manifest.insert(added.path().to_string(), entry);
+            }
+        }
+        // Record skipped duplicates too, or they would be embedded again on
+        // every sync. A modified file that now duplicates another image gives
+        // up the point it owned.
+        let skipped = ingested
+            .duplicates
+            .iter()
+            .filter(|duplicate| duplicate.action == DuplicatePolicy::Skip);
+        for duplicate in skipped {
+            if let Some((size, mtime, hash)) = stamps.remove(&duplicate.path) {
+                let entry = ManifestEntry {
+                    id: duplicate.existing.clone(),
+                    size,
+                    mtime,
+                    hash,
+                    duplicate: true,
+                };
+                orphans.extend(previous.remove(&duplicate.path));
+                manifest.insert(duplicate.path.clone(), entry);
+            }
+        }
+        report.skipped.extend(ingested.skipped);
+        report.duplicates.extend(ingested.duplicates);
+
+        for path in gone {
+            if let Some(entry) = manifest.remove(path) {
+                if !entry.duplicate {
+                    orphans.push(entry.id);
+                }
+                report.removed += 1;
+            }
+        }
+        orphans.retain(|id| !manifest.claim(id));
+        orphans.sort();
+        orphans.dedup();
+        if !orphans.is_empty() {
+            self.store.delete(&orphans).await?;
+        }
+        match failure {
+            Some(error) => Err(error),
+            None => Ok(report),
+        }
+    }
+
+    /// Id of a file being synced. Random ids stay stable across
+    /// modifications, derived ones follow the file.
+    fn synced_id(&self, path: &std::path::Path, hash: &str, previous: Option<String>) -> String {
+        match self.ids {
+            IdStrategy::Random => previous.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            IdStrategy::Content => content_id(hash),
+            IdStrategy::Path => path_id(path),
+        }
     }
 
     pub async fn add_images_with_extra<
-        T: Serialize + DeserializeOwned + Clone,
-        P: AsRef<std::path::Path>,
+        T: Serialize + DeserializeOwned + Clone + Sync,
+        P: AsRef<std::path::Path> + Sync,
     >(
         &self,
         paths: &[P],
         extras: &[T],
-    ) -> Result<()> {
-        let info = paths
+    ) -> std::result::Result<IngestReport<T>, IngestError<T>> {
+        let extras = extras.iter().map(|extra| Some(extra.to_owned()));
+        let mut skipped = Vec::new();
+        let (paths, info) = self.assign_ids(paths, extras, &mut skipped)?;
+        let mut result = self.ingest(&paths, info).await;
+        match &mut result {
+            Ok(report) | Err(IngestError { report, .. }) => report.skipped.splice(0..0, skipped),
+        };
+        result
+    }
+
+    /// Pairs each path with an `ImageInfo` under its [`point_id`](Self::point_id).
+    /// Files that cannot be read to derive the id are added to `skipped`.
+    fn assign_ids<'a, T, P: AsRef<std::path::Path>>(
+        &self,
+        paths: &'a [P],
+        extras: impl Iterator<Item = Option<T>>,
+        skipped: &mut Vec<Skipped>,
+    ) -> Result<(Vec<&'a P>, Vec<ImageInfo<T>>)> {
+        let mut kept = Vec::new();
+        let mut info = Vec::new();
+        for (path, extra) in paths.iter().zip(extras) {
+            let name = path.as_ref().to_string_lossy().to_string();
+            match self.point_id(path) {
+                Ok(id) => {
+                    kept.push(path);
+                    info.push(ImageInfo::new(&id, &name, extra));
+                }
+                Err(e) => skipped.push(Skipped {
+                    path: name,
+                    error: ItemError::classify(e)?,
+                }),
+            }
+        }
+        Ok((kept, info))
+    }
+
+    async fn ingest<T: Serialize + Sync, P: AsRef<std::path::Path> + Sync>(
+        &self,
+        paths: &[P],
+        info: Vec<ImageInfo<T>>,
+    ) -> std::result::Result<IngestReport<T>, IngestError<T>> {
+        let chunks = self.extractor().extract_hashed_chunks(paths);
+        self.ingest_chunks(chunks, info).await
+    }
+
+    /// Stamps, checks for duplicates and stores the extracted `chunks`,
+    /// pairing their items with `info` in order. A failure keeps the report
+    /// of the chunks already stored.
+    async fn ingest_chunks<'a, I: 'a, T: Serialize + Sync>(
+        &self,
+        chunks: impl Iterator<Item = Chunk<'a, I, HashedFeature>>,
+        info: Vec<ImageInfo<T>>,
+    ) -> std::result::Result<IngestReport<T>, IngestError<T>> {
+        let version = self.preprocess_version();
+        let mut report = IngestReport::default();
+        let mut info = info.into_iter();
+        // Store chunk by chunk so at most one chunk of features is held.
+        for chunk in chunks {
+            let stored = match chunk {
+                Ok(chunk) => self.store_chunk(chunk, &mut info, &version).await,
+                Err(error) => Err(error),
+            };
+            match stored {
+                Ok(stored) => {
+                    report.added.extend(stored.added);
+                    report.skipped.extend(stored.skipped);
+                    report.duplicates.extend(stored.duplicates);
+                }
+                Err(error) => return Err(report.interrupted(error)),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Stores one extracted chunk, taking its `ImageInfo`s from `info`.
+    async fn store_chunk<I, T: Serialize + Sync>(
+        &self,
+        chunk: Vec<(&I, ItemResult<HashedFeature>)>,
+        info: &mut impl Iterator<Item = ImageInfo<T>>,
+        version: &str,
+    ) -> Result<IngestReport<T>> {
+        let mut report = IngestReport::default();
+        let mut features = Vec::new();
+        for ((_, result), info) in chunk.into_iter().zip(info) {
+            match result {
+                Ok((feature, hash)) => {
+                    let mut info = info.with_preprocess(version).with_hash(hash);
+                    if let Some(check) = self.duplicates {
+                        let found =
+                            self.find_duplicate(&check, &feature, &info, &features, &report.added);
+                        if let Some((existing, score)) = found.await? {
+                            report.duplicates.push(DuplicateMatch {
+                                path: info.path().to_string(),
+                                existing: existing.clone(),
+                                score,
+                                action: check.policy(),
+                            });
+                            match check.policy() {
+                                DuplicatePolicy::Skip => continue,
+                                DuplicatePolicy::Replace => info.id = existing,
+                                DuplicatePolicy::Link => info.duplicate_of = Some(existing),
+                            }
+                        }
+                    }
+                    features.push(feature);
+                    report.added.push(info);
+                }
+                Err(error) => report.skipped.push(Skipped {
+                    path: info.path().to_string(),
+                    error,
+                }),
+            }
+        }
+        if !features.is_empty() {
+            self.store.add(&features, &report.added).await?;
+        }
+        Ok(report)
+    }
+
+    /// The most similar point to `feature` within the threshold, among
+    /// the stored ones and those `pending` in the current chunk. A point with
+    /// the same id as `info` is the image itself being re-indexed and does
+    /// not count.
+    async fn find_duplicate<T>(
+        &self,
+        check: &DuplicateCheck,
+        feature: &[f32],
+        info: &ImageInfo<T>,
+        pending_features: &[Vec<f32>],
+        pending: &[ImageInfo<T>],
+    ) -> Result<Option<(String, f32)>> {
+        let metric = self.store.metric();
+        let local = pending_features
             .iter()
-            .zip(extras)
-            .map(|(path, extra)| {
-                ImageInfo::with_extra(&path.as_ref().to_string_lossy(), extra.to_owned())
+            .zip(pending)
+            .map(|(other, other_info)| (other_info.id().to_string(), metric.score(feature, other)));
+        let stored = self
+            .search_feature::<serde::de::IgnoredAny>(feature, 2)
+            .await?
+            .into_iter()
+            .map(|hit| (hit.id().to_string(), hit.score()));
+        Ok(stored
+            .chain(local)
+            .filter(|(id, score)| id != info.id() && metric.within(*score, check.threshold()))
+            .max_by(|a, b| metric.closeness(a.1, b.1)))
+    }
+
+    /// Indexes an encoded image held in memory. `info` supplies the logical
+    /// path (an upload name, an archive member, ...), the id and any extra.
+    /// The report holds the image either in `added` or, when the duplicate
+    /// check kept it out, only in `duplicates`. An image that cannot be
+    /// decoded is an error.
+    pub async fn add_image_bytes<T: Serialize + Sync>(
+        &self,
+        bytes: &[u8],
+        info: ImageInfo<T>,
+    ) -> Result<IngestReport<T>> {
+        let mut report = self.add_images_bytes(&[bytes], vec![info]).await?;
+        match report.skipped.pop() {
+            Some(skipped) => Err(skipped.error.into()),
+            None => Ok(report),
+        }
+    }
+
+    /// Indexes encoded images held in memory, pairing `buffers` with `info`
+    /// in order. Buffers that cannot be decoded are skipped and listed in
+    /// the report under the path of their `info`, the rest are stored.
+    pub async fn add_images_bytes<T: Serialize + Sync, B: AsRef<[u8]> + Sync>(
+        &self,
+        buffers: &[B],
+        info: Vec<ImageInfo<T>>,
+    ) -> std::result::Result<IngestReport<T>, IngestError<T>> {
+        let chunks = self.extractor().extract_bytes_hashed_chunks(buffers);
+        self.ingest_chunks(chunks, info).await
+    }
+
+    /// Stored images whose perceptual hash is within `max_distance` of the
+    /// image at `path`, closest first. A distance of 0 to 4 catches
+    /// re-encodes and resizes; larger values start matching edits.
+    ///
+    /// Every point is scanned, since Hamming distances cannot be evaluated
+    /// by the store. Points indexed without a hash are ignored.
+    pub async fn find_duplicates<T: DeserializeOwned + Send, P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        max_distance: u32,
+    ) -> Result<Vec<Duplicate<T>>> {
+        let query = ImageHash::compute(&load_image(path)?);
+        let mut duplicates = Vec::new();
+        self.for_each_point(|info: ImageInfo<T>| {
+            let distance = info.hash().map(|hash| hash.distance(&query));
+            if let Some(distance) = distance.filter(|distance| *distance <= max_distance) {
+                duplicates.push(Duplicate { info, distance });
+            }
+        })
+        .await?;
+        duplicates.sort_by_key(|duplicate| duplicate.distance);
+        Ok(duplicates)
+    }
+
+    /// Groups the whole collection into clusters of images within
+    /// `max_distance` of each other, see [`find_duplicates`](Self::find_duplicates).
+    pub async fn duplicate_report<T: DeserializeOwned + Send>(
+        &self,
+        max_distance: u32,
+    ) -> Result<DuplicateReport<T>> {
+        let mut hashed = Vec::new();
+        let mut total = 0;
+        self.for_each_point(|info: ImageInfo<T>| {
+            total += 1;
+            if let Some(hash) = info.hash().copied() {
+                hashed.push((hash, info));
+            }
+        })
+        .await?;
+        let unhashed = total - hashed.len();
+        Ok(DuplicateReport {
+            clusters: cluster(hashed, max_distance),
+            total,
+            unhashed,
+        })
+    }
+
+    async fn for_each_point<T: DeserializeOwned + Send>(
+        &self,
+        mut f: impl FnMut(ImageInfo<T>),
+    ) -> Result<()> {
+        let mut offset = None;
+        loop {
+            let page = self.store.scroll::<T>(offset.as_deref(), 256).await?;
+            page.items.into_iter().for_each(&mut f);
+            match page.next_offset {
+                Some(next) => offset = Some(next),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    pub async fn delete(&self, ids: &[String]) -> Result<()> {
+        self.store.delete(ids).await
+    }
+
+    pub async fn delete_where(&self, filter: &PayloadFilter) -> Result<()> {
+        self.store.delete_where(filter).await
+    }
+
+    pub async fn get<T: DeserializeOwned + Send>(&self, ids: &[&str]) -> Result<Vec<ImageInfo<T>>> {
+        self.store.get(ids).await
+    }
+
+    pub async fn scroll<T: DeserializeOwned + Send>(
+        &self,
+        offset: Option<&str>,
+        limit: usize,
+    ) -> Result<ScrollPage<T>> {
+        self.store.scroll(offset, limit).await
+    }
+
+    /// Like [`App::scroll`], only returning points whose payload matches
+    /// `filter`.
+    pub async fn scroll_with<T: DeserializeOwned + Send>(
+        &self,
+        offset: Option<&str>,
+        limit: usize,
+        filter: &PayloadFilter,
+    ) -> Result<ScrollPage<T>> {
+        self.store.scroll_with(offset, limit, Some(filter)).await
+    }
+
+    pub async fn search<T: DeserializeOwned + Send, P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        k: usize,
+    ) -> Result<Vec<SearchHit<T>>> {
+        self.search_with(path, SearchOptions::new(k)).await
+    }
+
+    pub async fn search_with<T: DeserializeOwned + Send, P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        options: SearchOptions,
+    ) -> Result<Vec<SearchHit<T>>> {
+        let feature = self.extractor().extract(path)?;
+        self.search_feature_with(&feature, options).await
+    }
+
+    pub async fn search_image<T: DeserializeOwned + Send>(
+        &self,
+        image: &DynamicImage,
+        k: usize,
+    ) -> Result<Vec<SearchHit<T>>> {
+        self.search_image_with(image, SearchOptions::new(k)).await
+    }
+
+    pub async fn search_image_with<T: DeserializeOwned + Send>(
+        &self,
+        image: &DynamicImage,
+        options: SearchOptions,
+    ) -> Result<Vec<SearchHit<T>>> {
+        let feature = self.extractor().extract_image(image)?;
+        self.search_feature_with(&feature, options).await
+    }
+
+    pub async fn search_bytes<T: DeserializeOwned + Send>(
+        &self,
+        bytes: &[u8],
+        k: usize,
+    ) -> Result<Vec<SearchHit<T>>> {
+        self.search_bytes_with(bytes, SearchOptions::new(k)).await
+    }
+
+    pub async fn search_bytes_with<T: DeserializeOwned + Send>(
+        &self,
+        bytes: &[u8],
+        options: SearchOptions,
+    ) -> Result<Vec<SearchHit<T>>> {
+        let feature = self.extractor().extract_bytes(bytes)?;
+        self.search_feature_with(&feature, options).await
+    }
+
+    /// Finds the images best described by `text`. Needs a network with a
+    /// text encoder such as [`NetworkKind::Clip`](crate::config::NetworkKind::Clip),
+    /// and a collection indexed with that same network.
+    pub async fn search_text<T: DeserializeOwned + Send>(
+        &self,
+        text: &str,
+        k: usize,
+    ) -> Result<Vec<SearchHit<T>>> {
+        self.search_text_with(text, SearchOptions::new(k)).await
+    }
+
+    pub async fn search_text_with<T: DeserializeOwned + Send>(
+        &self,
+        text: &str,
+        options: SearchOptions,
+    ) -> Result<Vec<SearchHit<T>>> {
+        let feature = self.extractor().extract_text(text)?;
+        self.search_feature_with(&feature, options).await
+    }
+
+    /// Searches with a feature produced by this app's extractor. Points that
+    /// were stored by a different preprocessing pipeline are left out, their
+    /// scores are not comparable. That includes points stored before the
+    /// pipeline was recorded, which have no `preprocess` at all; re-embed
+    /// them with [`App::migrate_from`].
+    pub async fn search_feature<T: DeserializeOwned + Send>(
+        &self,
+        feature: &[f32],
+        k: usize,
+    ) -> Result<Vec<SearchHit<T>>> {
+        self.search_feature_with(feature, SearchOptions::new(k))
+            .await
+    }
+
+    /// Like [`App::search_feature`], with the paging, score threshold and
+    /// index settings of `options`.
+    pub async fn search_feature_with<T: DeserializeOwned + Send>(
+        &self,
+        feature: &[f32],
+        options: SearchOptions,
+    ) -> Result<Vec<SearchHit<T>>> {
+        // Filtered by the store, so paging and thresholds see the same
+        // points the hits are drawn from.
+        let mut filter =
+            PayloadFilter::new().must(Condition::matches("preprocess", self.preprocess_version()));
+        if let Some(wanted) = options.filter() {
+            filter = filter.must(wanted.clone());
+        }
+        self.store
+            .search_with(feature, options.with_filter(filter))
+            .await
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{model::tests::MeanColor, store::MemoryStore, utils::tests::TempDir};
+    use candle_core::Device;
+    use std::sync::Arc;
+
+    fn app() -> App<MemoryStore> {
+        let extractor = Extractor::from_model(Arc::new(MeanColor), &Device::Cpu);
+        App::with_store(MemoryStore::new(2), extractor)
+    }
+
+    fn checked_app(policy: DuplicatePolicy) -> App<MemoryStore> {
+        app().with_duplicate_check(DuplicateCheck::new(policy, 0.95))
+    }
+
+    /// A folder holding a red `a.png`, its copy `b.png` and a green `c.png`.
+    pub(crate) fn images() -> (TempDir, Vec<std::path::PathBuf>) {
+        let dir = TempDir::new("app");
+        let paths = [("a", [255, 0, 0]), ("b", [255, 0, 0]), ("c", [0, 255, 0])]
+            .into_iter()
+            .map(|(name, color)| {
+                let path = dir.join(format!("{name}.png"));
+                image::RgbImage::from_pixel(16, 16, image::Rgb(color))
+                    .save(&path)
+                    .unwrap();
+                path
             })
-            .collect::<Vec<ImageInfo<T>>>();
-        let features = self.extractor().extract_batch(paths)?;
-        database::add(&self.db, &self.collection, &features, &info).await
+            .collect();
+        (dir, paths)
+    }
+
+    #[tokio::test]
+    async fn test_search_leaves_out_other_pipelines() {
+        let app = app();
+        let version = app.preprocess_version();
+        let infos = [
+            ImageInfo::<()>::new("other", "other.png", None).with_preprocess("v0"),
+            ImageInfo::new("current", "current.png", None).with_preprocess(&version),
+            ImageInfo::new("legacy", "legacy.png", None),
+        ];
+        let features = [vec![1.0, 0.0], vec![1.0, 0.1], vec![1.0, 0.2]];
+        app.store().add(&features, &infos).await.unwrap();
+
+        let hits = app.search_feature::<()>(&[1.0, 0.0], 3).await.unwrap();
+        let ids = hits.iter().map(SearchHit::id).collect::<Vec<_>>();
+        assert_eq!(ids, ["current"]);
+
+        let options = SearchOptions::new(1).with_offset(1);
+        let hits = app
+            .search_feature_with::<()>(&[1.0, 0.0], options)
+            .await
+            .unwrap();
+        assert!(hits.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_pages_with_threshold() {
+        let app = app();
+        let version = app.preprocess_version();
+        let mut features = Vec::new();
+        let mut infos = Vec::new();
+        for (i, y) in [0.0, 0.05, 0.1, 0.15, 0.4].into_iter().enumerate() {
+            features.push(vec![1.0, y]);
+            infos.push(
+                ImageInfo::<()>::new(&format!("p{i}"), "p.png", None).with_preprocess(&version),
+            );
+            // Points from another pipeline ranked right after each one.
+            features.push(vec![1.0, y + 0.02]);
+            infos.push(ImageInfo::new(&format!("o{i}"), "o.png", None).with_preprocess("v0"));
+        }
+        app.store().add(&features, &infos).await.unwrap();
+
+        // Pages are cut from the matching points only, so they neither
+        // overlap nor leave gaps, and the threshold ends the last one.
+        let mut ids = Vec::new();
+        for page in 0..3 {
+            let options = SearchOptions::new(2)
+                .with_offset(page * 2)
+                .with_score_threshold(0.95);
+            let hits = app
+                .search_feature_with::<()>(&[1.0, 0.0], options)
+                .await
+                .unwrap();
+            assert_eq!(hits.len(), if page < 2 { 2 } else { 0 });
+            ids.extend(hits.iter().map(|hit| hit.id().to_string()));
+        }
+        assert_eq!(ids, ["p0", "p1", "p2", "p3"]);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_skip() {
+        let (_dir, paths) = images();
+        let app = checked_app(DuplicatePolicy::Skip);
+
+        // `b.png` matches `a.png` in the same chunk.
+        let report = app.add_images(&paths).await.unwrap();
+        assert_eq!(report.added.len(), 2);
+        assert_eq!(report.duplicates.len(), 1);
+        let red = report.added[0].id().to_string();
+        assert!(report.duplicates[0].path.ends_with("b.png"));
+        assert_eq!(report.duplicates[0].existing, red);
+        assert_eq!(app.store().len(), 2);
+
+        // And the stored `a.png` afterwards.
+        let report = app.add_images(&paths[1..2]).await.unwrap();
+        assert!(report.added.is_empty());
+        assert_eq!(report.duplicates[0].existing, red);
+        assert_eq!(app.store().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_replace_and_link() {
+        let (_dir, paths) = images();
+        let app = checked_app(DuplicatePolicy::Replace);
+        let report = app.add_images(&paths).await.unwrap();
+        assert_eq!(report.added.len(), 3);
+        assert_eq!(report.added[1].id(), report.added[0].id());
+        assert_eq!(app.store().len(), 2);
+        let stored = app.store().get::<()>(&[report.added[0].id()]).await;
+        assert!(stored.unwrap()[0].path().ends_with("b.png"));
+
+        let app = checked_app(DuplicatePolicy::Link);
+        let report = app.add_images(&paths).await.unwrap();
+        assert_eq!(report.added.len(), 3);
+        assert_eq!(app.store().len(), 3);
+        let linked = app.store().get::<()>(&[report.added[1].id()]).await;
+        assert_eq!(
+            linked.unwrap()[0].duplicate_of(),
+            Some(report.added[0].id())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_check_covers_bytes() {
+        let (_dir, paths) = images();
+        let app = checked_app(DuplicatePolicy::Skip);
+        app.add_images(&paths[..1]).await.unwrap();
+
+        let bytes = std::fs::read(&paths[1]).unwrap();
+        let info = ImageInfo::<()>::new("upload", "upload.png", None);
+        let report = app.add_image_bytes(&bytes, info).await.unwrap();
+        assert!(report.added.is_empty());
+        assert_eq!(report.duplicates.len(), 1);
+        assert_eq!(report.duplicates[0].path, "upload.png");
+        assert_eq!(report.duplicates[0].action, DuplicatePolicy::Skip);
+        assert_eq!(app.store().len(), 1);
+
+        let bytes = std::fs::read(&paths[2]).unwrap();
+        let info = ImageInfo::<()>::new("green", "green.png", None);
+        let report = app.add_image_bytes(&bytes, info).await.unwrap();
+        assert_eq!(report.added.len(), 1);
+        assert!(report.duplicates.is_empty());
+        assert_eq!(app.store().len(), 2);
+
+        let report = app
+            .add_images_bytes(
+                &[&b"not an image"[..], &bytes[..]],
+                vec![
+                    ImageInfo::<()>::new("x", "x.png", None),
+                    ImageInfo::<()>::new("y", "y.png", None),
+                ],
+            )
+            .await
+            .unwrap();
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].path, "x.png");
+        assert_eq!(report.duplicates[0].path, "y.png");
+        assert_eq!(app.store().len(), 2);
+
+        let info = ImageInfo::<()>::new("z", "z.png", None);
+        assert!(app.add_image_bytes(b"not an image", info).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sync_records_skipped_duplicates() {
+        let (dir, paths) = images();
+        let manifest = dir.join("manifest.json");
+        let scan = FolderScan::default();
+        let app = checked_app(DuplicatePolicy::Skip);
+
+        let report = app.sync_folder(&dir, &scan, &manifest).await.unwrap();
+        assert_eq!(report.added, 2);
+        assert_eq!(report.duplicates.len(), 1);
+
+        // The skipped copy is not embedded again.
+        let report = app.sync_folder(&dir, &scan, &manifest).await.unwrap();
+        assert_eq!(report.unchanged, 3);
+        assert!(report.duplicates.is_empty());
+
+        // Its point outlives the original while the copy remains.
+        std::fs::remove_file(&paths[0]).unwrap();
+        let report = app.sync_folder(&dir, &scan, &manifest).await.unwrap();
+        assert_eq!(report.removed, 1);
+        assert_eq!(app.store().len(), 2);
+        std::fs::remove_file(&paths[1]).unwrap();
+        app.sync_folder(&dir, &scan, &manifest).await.unwrap();
+        assert_eq!(app.store().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_chunk_keeps_earlier_report() {
+        /// An extra whose payload cannot be built when it is `false`.
+        #[derive(Debug, Clone, Deserialize)]
+        struct Storable(bool);
+
+        impl Serialize for Storable {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                match self.0 {
+                    true => serializer.serialize_bool(true),
+                    false => Err(serde::ser::Error::custom("not storable")),
+                }
+            }
+        }
+
+        let (_dir, paths) = images();
+        let extractor =
+            Extractor::from_model(Arc::new(MeanColor), &Device::Cpu).with_max_batch_size(1);
+        let app = App::with_store(MemoryStore::new(2), extractor);
+        let extras = [Storable(true), Storable(false), Storable(true)];
+        let err = app
+            .add_images_with_extra(&paths, &extras)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error, Error::SerdeError(_)));
+        assert_eq!(err.report.added.len(), 1);
+        assert!(err.report.added[0].path().ends_with("a.png"));
+        assert_eq!(app.store().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_sync_paths_applies_changed_paths_only() {
+        let (dir, paths) = images();
+        let manifest = dir.join("manifest.json");
+        let scan = FolderScan::default().exclude("new/skip.png");
+        let app = app();
+        app.sync_folder(&dir, &scan, &manifest).await.unwrap();
+
+        let new = dir.join("new");
+        std::fs::create_dir(&new).unwrap();
+        for name in ["d.png", "skip.png"] {
+            std::fs::copy(&paths[2], new.join(name)).unwrap();
+        }
+        std::fs::remove_file(&paths[2]).unwrap();
+        std::fs::remove_file(&paths[0]).unwrap();
+
+        // `a.png` is gone too, but not among the changed paths.
+        let changed = [paths[2].clone(), new];
+        let report = app
+            .sync_paths(&dir, &scan, &changed, &manifest)
+            .await
+            .unwrap();
+        assert_eq!(report.added, 1);
+        assert_eq!(report.removed, 1);
+        assert_eq!(app.store().len(), 3);
+        let manifest = Manifest::load(&manifest).unwrap();
+        let synced = manifest.paths().collect::<Vec<_>>();
+        assert_eq!(synced.len(), 3);
+        assert!(synced[0].ends_with("a.png"));
+        assert!(synced[2].ends_with("new/d.png"));
+    }
+
+    /// A [`MemoryStore`] whose `fail_at`-th call to `add` fails.
+    pub(crate) struct FailingStore {
+        pub(crate) inner: MemoryStore,
+        pub(crate) adds: std::sync::atomic::AtomicUsize,
+        pub(crate) fail_at: usize,
+    }
+
+    impl VectorStore for FailingStore {
+        async fn add<T: Serialize + Sync>(
+            &self,
+            data: &[Vec<f32>],
+            image_info: &[ImageInfo<T>],
+        ) -> Result<()> {
+            let n = self.adds.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            if n == self.fail_at {
+                return Err(Error::UpsertPointsError("unavailable".to_string()));
+            }
+            self.inner.add(data, image_info).await
+        }
+
+        async fn delete(&self, ids: &[String]) -> Result<()> {
+            self.inner.delete(ids).await
+        }
+
+        async fn delete_where(&self, filter: &PayloadFilter) -> Result<()> {
+            self.inner.delete_where(filter).await
+        }
+
+        async fn get<T: DeserializeOwned + Send>(&self, ids: &[&str]) -> Result<Vec<ImageInfo<T>>> {
+            self.inner.get(ids).await
+        }
+
+        async fn search_with<T: DeserializeOwned + Send>(
+            &self,
+            feature: &[f32],
+            options: SearchOptions,
+        ) -> Result<Vec<SearchHit<T>>> {
+            self.inner.search_with(feature, options).await
+        }
+
+        async fn scroll_with<T: DeserializeOwned + Send>(
+            &self,
+            offset: Option<&str>,
+            limit: usize,
+            filter: Option<&PayloadFilter>,
+        ) -> Result<ScrollPage<T>> {
+            self.inner.scroll_with(offset, limit, filter).await
+        }
+
+        async fn metadata(&self) -> Result<Option<CollectionMeta>> {
+            self.inner.metadata().await
+        }
+
+        async fn set_metadata(&self, meta: &CollectionMeta) -> Result<()> {
+            self.inner.set_metadata(meta).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_sync_saves_stored_files() {
+        let (dir, _) = images();
+        let manifest = dir.join("manifest.json");
+        let scan = FolderScan::default();
+        let store = FailingStore {
+            inner: MemoryStore::new(2),
+            adds: Default::default(),
+            fail_at: 1,
+        };
+        let extractor =
+            Extractor::from_model(Arc::new(MeanColor), &Device::Cpu).with_max_batch_size(1);
+        let app = App::with_store(store, extractor);
+
+        let err = app.sync_folder(&dir, &scan, &manifest).await.unwrap_err();
+        assert!(matches!(err, Error::UpsertPointsError(_)));
+        assert_eq!(app.store().inner.len(), 1);
+
+        // Only the files that were not stored are embedded again.
+        let report = app.sync_folder(&dir, &scan, &manifest).await.unwrap();
+        assert_eq!(report.unchanged, 1);
+        assert_eq!(report.added, 2);
+        assert_eq!(app.store().inner.len(), 3);
     }
 }
@@ -1,8 +1,16 @@
-use crate::error::{Error, Result};
+use crate::{
+    error::{Error, Result},
+    preprocess::PreprocessConfig,
+    store::{dot, normalize},
+};
 use candle_transformers::models::mobilenetv4;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum NetworkKind {
     Small,
@@ -11,6 +19,11 @@ pub enum NetworkKind {
     HybridMedium,
     #[default]
     HybridLarge,
+    /// OpenAI CLIP ViT-B/32. Its images and texts share one embedding space,
+    /// so it can also be searched with words.
+    Clip,
+    ConvnextTiny,
+    ConvnextBase,
 }
 
 impl NetworkKind {
@@ -21,10 +34,27 @@ impl NetworkKind {
             Self::HybridMedium => "hybrid_medium.ix_e550_r256",
             Self::Large => "conv_large.e600_r384",
             Self::HybridLarge => "hybrid_large.ix_e600_r384",
+            Self::Clip => return "openai/clip-vit-base-patch32".to_string(),
+            Self::ConvnextTiny => return "timm/convnext_tiny.fb_in1k".to_string(),
+            Self::ConvnextBase => return "timm/convnext_base.fb_in1k".to_string(),
         };
         format!("timm/mobilenetv4_{}_in1k", name)
     }
 
+    /// Hub revision holding the safetensors weights.
+    pub fn revision(&self) -> &'static str {
+        match self {
+            Self::Clip => "refs/pr/15",
+            _ => "main",
+        }
+    }
+
+    /// Whether the network has a text encoder, see
+    /// [`Extractor::extract_text`](crate::extractor::Extractor::extract_text).
+    pub fn supports_text(&self) -> bool {
+        matches!(self, Self::Clip)
+    }
+
     pub fn resolution(&self) -> u32 {
         match self {
             Self::Small => 224,
@@ -32,28 +62,322 @@ impl NetworkKind {
             Self::HybridMedium => 256,
             Self::Large => 384,
             Self::HybridLarge => 384,
+            Self::Clip | Self::ConvnextTiny | Self::ConvnextBase => 224,
+        }
+    }
+
+    /// Per-channel `(mean, std)` the checkpoint was trained with.
+    pub fn normalization(&self) -> ([f32; 3], [f32; 3]) {
+        match self {
+            Self::Clip => (
+                [0.481_454_66, 0.457_827_5, 0.408_210_73],
+                [0.268_629_54, 0.261_302_6, 0.275_777_1],
+            ),
+            _ => ([0.485, 0.456, 0.406], [0.229, 0.224, 0.225]),
+        }
+    }
+
+    /// The MobileNetV4 architecture, `None` for other networks.
+    pub(crate) fn mobilenet_config(&self) -> Option<mobilenetv4::Config> {
+        match self {
+            Self::Small => Some(mobilenetv4::Config::small()),
+            Self::Medium => Some(mobilenetv4::Config::medium()),
+            Self::HybridMedium => Some(mobilenetv4::Config::hybrid_medium()),
+            Self::Large => Some(mobilenetv4::Config::large()),
+            Self::HybridLarge => Some(mobilenetv4::Config::hybrid_large()),
+            Self::Clip | Self::ConvnextTiny | Self::ConvnextBase => None,
         }
     }
+}
+
+/// How [`DbConfig::collection`] maps to Qdrant collections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layout {
+    /// `collection` is the physical collection.
+    #[default]
+    Direct,
+    /// `collection` is an alias over versioned physical collections, see
+    /// [`QdrantStore`](crate::store::QdrantStore).
+    Alias,
+}
+
+/// How two vectors are compared. The embedded and memory backends only
+/// support cosine similarity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    #[default]
+    Cosine,
+    Dot,
+    Euclid,
+    Manhattan,
+}
+
+impl Metric {
+    /// Whether scores are similarities, higher meaning closer. Euclid and
+    /// Manhattan scores are distances, lower meaning closer.
+    pub fn is_similarity(&self) -> bool {
+        matches!(self, Self::Cosine | Self::Dot)
+    }
 
-    pub(crate) fn config(&self) -> mobilenetv4::Config {
+    /// The score Qdrant gives `b` for the query `a`.
+    pub fn score(&self, a: &[f32], b: &[f32]) -> f32 {
+        let pairs = a.iter().zip(b);
         match self {
-            Self::Small => mobilenetv4::Config::small(),
-            Self::Medium => mobilenetv4::Config::medium(),
-            Self::HybridMedium => mobilenetv4::Config::hybrid_medium(),
-            Self::Large => mobilenetv4::Config::large(),
-            Self::HybridLarge => mobilenetv4::Config::hybrid_large(),
+            Self::Cosine => dot(&normalize(a), &normalize(b)),
+            Self::Dot => pairs.map(|(x, y)| x * y).sum(),
+            Self::Euclid => pairs.map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt(),
+            Self::Manhattan => pairs.map(|(x, y)| (x - y).abs()).sum(),
+        }
+    }
+
+    /// Whether `score` is at least as close as `threshold`.
+    pub fn within(&self, score: f32, threshold: f32) -> bool {
+        if self.is_similarity() {
+            score >= threshold
+        } else {
+            score <= threshold
+        }
+    }
+
+    /// Orders scores from farthest to closest, so the closest is the maximum.
+    pub fn closeness(&self, a: f32, b: f32) -> std::cmp::Ordering {
+        if self.is_similarity() {
+            a.total_cmp(&b)
+        } else {
+            b.total_cmp(&a)
+        }
+    }
+}
+
+/// How much product quantization shrinks the vectors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    X4,
+    X8,
+    #[default]
+    X16,
+    X32,
+    X64,
+}
+
+/// Compressed copy of the vectors Qdrant searches before rescoring.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Quantization {
+    /// One byte per dimension.
+    Scalar {
+        /// Share of values kept inside the quantization range, e.g. `0.99`.
+        #[serde(default)]
+        quantile: Option<f32>,
+        #[serde(default)]
+        always_ram: bool,
+    },
+    Product {
+        #[serde(default)]
+        compression: Compression,
+        #[serde(default)]
+        always_ram: bool,
+    },
+}
+
+/// Parameters a Qdrant collection is created with. Unset values use the
+/// server defaults. Qdrant cannot change most of them afterwards, so an
+/// existing collection that differs is only reported, see
+/// [`QdrantStore::drift`](crate::store::QdrantStore::drift).
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(default)]
+pub struct CollectionParams {
+    distance: Metric,
+    /// Keep the vectors in memory-mapped files rather than in RAM.
+    on_disk: Option<bool>,
+    hnsw_m: Option<u64>,
+    hnsw_ef_construct: Option<u64>,
+    quantization: Option<Quantization>,
+    shard_number: Option<u32>,
+    replication_factor: Option<u32>,
+}
+
+impl CollectionParams {
+    pub fn with_distance(mut self, distance: Metric) -> Self {
+        self.distance = distance;
+        self
+    }
+
+    pub fn with_on_disk(mut self, on_disk: bool) -> Self {
+        self.on_disk = Some(on_disk);
+        self
+    }
+
+    pub fn with_hnsw(mut self, m: u64, ef_construct: u64) -> Self {
+        self.hnsw_m = Some(m);
+        self.hnsw_ef_construct = Some(ef_construct);
+        self
+    }
+
+    pub fn with_quantization(mut self, quantization: Quantization) -> Self {
+        self.quantization = Some(quantization);
+        self
+    }
+
+    pub fn with_shards(mut self, shard_number: u32, replication_factor: u32) -> Self {
+        self.shard_number = Some(shard_number);
+        self.replication_factor = Some(replication_factor);
+        self
+    }
+
+    pub fn distance(&self) -> Metric {
+        self.distance
+    }
+
+    pub fn on_disk(&self) -> Option<bool> {
+        self.on_disk
+    }
+
+    pub fn hnsw_m(&self) -> Option<u64> {
+        self.hnsw_m
+    }
+
+    pub fn hnsw_ef_construct(&self) -> Option<u64> {
+        self.hnsw_ef_construct
+    }
+
+    pub fn quantization(&self) -> Option<Quantization> {
+        self.quantization
+    }
+
+    pub fn shard_number(&self) -> Option<u32> {
+        self.shard_number
+    }
+
+    pub fn replication_factor(&self) -> Option<u32> {
+        self.replication_factor
+    }
+}
+
+/// Where image features are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    /// A Qdrant server reached through `url` and `port`.
+    #[default]
+    Qdrant,
+    /// Files under `path`, no external database needed.
+    Embedded,
+    /// Process memory only, lost on exit.
+    Memory,
+}
+
+/// How the embedded backend answers searches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexKind {
+    /// Exact scan over every vector.
+    #[default]
+    Flat,
+    /// Approximate search through an [`HnswConfig`] graph.
+    Hnsw,
+}
+
+/// How point ids are assigned to indexed files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdStrategy {
+    /// A fresh UUIDv4 per insert, so indexing a file twice stores it twice.
+    #[default]
+    Random,
+    /// UUIDv5 of the SHA-256 of the file content. Re-indexing is an upsert
+    /// and identical files share one point, whatever their path.
+    Content,
+    /// UUIDv5 of the absolute path. Re-indexing a path replaces its point.
+    Path,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(default)]
+pub struct HnswConfig {
+    m: usize,
+    ef_construction: usize,
+    ef_search: usize,
+}
+
+impl HnswConfig {
+    pub fn new(m: usize, ef_construction: usize, ef_search: usize) -> Self {
+        Self {
+            m,
+            ef_construction,
+            ef_search,
+        }
+    }
+
+    /// Links per node on the upper layers, twice as many on layer 0.
+    pub fn m(&self) -> usize {
+        self.m
+    }
+
+    pub fn ef_construction(&self) -> usize {
+        self.ef_construction
+    }
+
+    pub fn ef_search(&self) -> usize {
+        self.ef_search
+    }
+}
+
+impl Default for HnswConfig {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 200,
+            ef_search: 64,
         }
     }
 }
 
+/// A value kept out of `Debug` output, so configs can be logged.
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+struct Secret(String);
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("\"<redacted>\"")
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct DbConfig {
+    backend: Backend,
+    /// Qdrant host, or a full URL such as `https://xyz.cloud.qdrant.io:6334`
+    /// in which case `port` is not used. An `https` server is checked against
+    /// the platform roots; to trust a custom CA, start the process with
+    /// `SSL_CERT_FILE` naming its PEM file, which replaces those roots.
     url: String,
     port: u16,
+    api_key: Option<Secret>,
+    /// Environment variable holding the API key.
+    api_key_env: Option<String>,
+    /// File holding the API key.
+    api_key_file: Option<PathBuf>,
+    connect_timeout_ms: u64,
+    timeout_ms: u64,
     collection: String,
+    layout: Layout,
+    params: CollectionParams,
+    path: PathBuf,
+    index: IndexKind,
+    hnsw: HnswConfig,
+    ids: IdStrategy,
 }
 
 impl DbConfig {
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
     pub fn url(&self) -> &str {
         &self.url
     }
@@ -62,29 +386,222 @@ impl DbConfig {
         self.port
     }
 
+    /// The URL the Qdrant client connects to. A bare host gets `http://`
+    /// and `port`.
+    pub fn qdrant_url(&self) -> String {
+        if self.url.contains("://") {
+            self.url.clone()
+        } else {
+            format!("http://{}:{}", self.url, self.port)
+        }
+    }
+
+    /// The API key, read from whichever of `api_key`, `api_key_env` and
+    /// `api_key_file` is set.
+    pub fn api_key(&self) -> Result<Option<String>> {
+        match (&self.api_key, &self.api_key_env, &self.api_key_file) {
+            (None, None, None) => Ok(None),
+            (Some(Secret(key)), None, None) => Ok(Some(key.clone())),
+            (None, Some(var), None) => std::env::var(var)
+                .map(Some)
+                .map_err(|e| Error::ConfigError(format!("api_key_env `{var}`: {e}"))),
+            (None, None, Some(file)) => std::fs::read_to_string(file)
+                .map(|key| Some(key.trim().to_string()))
+                .map_err(|e| Error::ConfigError(format!("api_key_file {}: {e}", file.display()))),
+            _ => Err(Error::ConfigError(
+                "set only one of api_key, api_key_env and api_key_file".to_string(),
+            )),
+        }
+    }
+
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_millis(self.connect_timeout_ms)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+
     pub fn collection(&self) -> &str {
         &self.collection
     }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn params(&self) -> CollectionParams {
+        self.params
+    }
+
+    pub fn with_params(mut self, params: CollectionParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = url.to_string();
+        self
+    }
+
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(Secret(api_key.to_string()));
+        self
+    }
+
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout_ms = timeout.as_millis() as u64;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout_ms = timeout.as_millis() as u64;
+        self
+    }
+
+    pub fn with_collection(mut self, collection: &str) -> Self {
+        self.collection = collection.to_string();
+        self
+    }
+
+    /// Directory holding the files of the embedded backend.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn index(&self) -> IndexKind {
+        self.index
+    }
+
+    pub fn hnsw(&self) -> HnswConfig {
+        self.hnsw
+    }
+
+    pub fn ids(&self) -> IdStrategy {
+        self.ids
+    }
 }
 
-#[derive(Debug, Clone, Default, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct MobilenetConfig {
     kind: NetworkKind,
     device: Device,
+    /// Explicit safetensors file, used instead of the HuggingFace hub.
+    #[serde(default)]
+    weights: Option<PathBuf>,
+    /// Explicit `tokenizer.json` for networks with a text encoder.
+    #[serde(default)]
+    tokenizer: Option<PathBuf>,
+    #[serde(default = "default_cache_dir")]
+    cache_dir: PathBuf,
+    /// Only look in `cache_dir`, never download.
+    #[serde(default)]
+    offline: bool,
+    #[serde(default)]
+    preprocess: PreprocessConfig,
+    /// Largest number of images decoded and run through the network at once.
+    #[serde(default = "default_max_batch_size")]
+    max_batch_size: usize,
+}
+
+fn default_max_batch_size() -> usize {
+    32
+}
+
+fn default_cache_dir() -> PathBuf {
+    PathBuf::from("./.cache")
 }
 
 impl MobilenetConfig {
     pub fn new(kind: NetworkKind, device: Device) -> Self {
-        Self { kind, device }
+        Self {
+            kind,
+            device,
+            weights: None,
+            tokenizer: None,
+            cache_dir: default_cache_dir(),
+            offline: false,
+            preprocess: PreprocessConfig::default(),
+            max_batch_size: default_max_batch_size(),
+        }
+    }
+
+    pub fn with_device(mut self, device: Device) -> Self {
+        self.device = device;
+        self
+    }
+
+    pub fn with_weights(mut self, weights: impl Into<PathBuf>) -> Self {
+        self.weights = Some(weights.into());
+        self
+    }
+
+    pub fn with_tokenizer(mut self, tokenizer: impl Into<PathBuf>) -> Self {
+        self.tokenizer = Some(tokenizer.into());
+        self
+    }
+
+    pub fn with_cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = cache_dir.into();
+        self
+    }
+
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn with_preprocess(mut self, preprocess: PreprocessConfig) -> Self {
+        self.preprocess = preprocess;
+        self
+    }
+
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size;
+        self
     }
 
     pub fn kind(&self) -> NetworkKind {
         self.kind
     }
 
+    pub fn preprocess(&self) -> PreprocessConfig {
+        self.preprocess
+    }
+
+    pub fn max_batch_size(&self) -> usize {
+        self.max_batch_size
+    }
+
     pub fn device(&self) -> Device {
         self.device
     }
+
+    pub fn weights(&self) -> Option<&Path> {
+        self.weights.as_deref()
+    }
+
+    pub fn tokenizer(&self) -> Option<&Path> {
+        self.tokenizer.as_deref()
+    }
+
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    /// `true` if `offline` is set or `HF_HUB_OFFLINE` is enabled in the
+    /// environment.
+    pub fn offline(&self) -> bool {
+        self.offline
+            || std::env::var("HF_HUB_OFFLINE")
+                .is_ok_and(|value| !matches!(value.as_str(), "" | "0"))
+    }
+}
+
+impl Default for MobilenetConfig {
+    fn default() -> Self {
+        Self::new(NetworkKind::default(), Device::default())
+    }
 }
 
 #[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize)]
@@ -120,9 +637,93 @@ impl Device {
 impl Default for DbConfig {
     fn default() -> Self {
         Self {
+            backend: Backend::default(),
             url: "127.0.0.1".to_string(),
             port: 6333,
+            api_key: None,
+            api_key_env: None,
+            api_key_file: None,
+            connect_timeout_ms: 30_000,
+            timeout_ms: 30_000,
             collection: "images".to_string(),
+            layout: Layout::default(),
+            params: CollectionParams::default(),
+            path: PathBuf::from("./.index"),
+            index: IndexKind::default(),
+            hnsw: HnswConfig::default(),
+            ids: IdStrategy::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TempDir;
+
+    #[test]
+    fn test_qdrant_url_and_api_key() {
+        let config = DbConfig::default();
+        assert_eq!(config.qdrant_url(), "http://127.0.0.1:6333");
+        assert_eq!(config.api_key().unwrap(), None);
+
+        let config = config.with_url("https://xyz.cloud.qdrant.io:6334");
+        assert_eq!(config.qdrant_url(), "https://xyz.cloud.qdrant.io:6334");
+
+        let dir = TempDir::new("key");
+        let file = dir.join("key");
+        std::fs::write(&file, "secret\n").unwrap();
+        let config = DbConfig {
+            api_key_file: Some(file.clone()),
+            ..DbConfig::default()
+        };
+        assert_eq!(config.api_key().unwrap().as_deref(), Some("secret"));
+        assert!(matches!(
+            config.with_api_key("other").api_key(),
+            Err(Error::ConfigError(_))
+        ));
+
+        let config = DbConfig::default().with_api_key("hunter2");
+        assert_eq!(config.api_key().unwrap().as_deref(), Some("hunter2"));
+        assert!(!format!("{config:?}").contains("hunter2"));
+    }
+
+    #[test]
+    fn test_collection_params() {
+        let params: CollectionParams = serde_json::from_value(serde_json::json!({
+            "distance": "dot",
+            "hnsw_m": 32,
+            "quantization": { "kind": "product", "compression": "x32" },
+        }))
+        .unwrap();
+        assert_eq!(params.distance(), Metric::Dot);
+        assert_eq!(params.hnsw_m(), Some(32));
+        assert_eq!(params.hnsw_ef_construct(), None);
+        assert_eq!(
+            params.quantization(),
+            Some(Quantization::Product {
+                compression: Compression::X32,
+                always_ram: false
+            })
+        );
+    }
+
+    #[test]
+    fn test_metric_direction() {
+        let (a, b) = ([3.0, 0.0], [0.0, 4.0]);
+        assert_eq!(Metric::Cosine.score(&a, &b), 0.0);
+        assert_eq!(Metric::Euclid.score(&a, &b), 5.0);
+        assert_eq!(Metric::Manhattan.score(&a, &b), 7.0);
+
+        assert!(Metric::Cosine.within(0.97, 0.95));
+        assert!(!Metric::Euclid.within(0.97, 0.95));
+        assert!(Metric::Euclid.within(0.1, 0.95));
+        let closest = |metric: Metric| {
+            [0.2, 0.9]
+                .into_iter()
+                .max_by(|a, b| metric.closeness(*a, *b))
+        };
+        assert_eq!(closest(Metric::Dot), Some(0.9));
+        assert_eq!(closest(Metric::Manhattan), Some(0.2));
+    }
+}
@@ -1,15 +1,64 @@
 use crate::{
     app::ImageInfo,
     error::{Error, Result},
+    store::SearchOptions,
 };
 use qdrant_client::{
     Payload, Qdrant,
     qdrant::{
-        Condition, DeletePointsBuilder, Filter, GetPointsBuilder, PointStruct, PointsIdsList,
-        QueryPointsBuilder, RetrievedPoint, ScoredPoint, UpsertPointsBuilder, r#match::MatchValue,
+        Condition, DeletePointsBuilder, Filter, GetPointsBuilder, PointId, PointStruct,
+        PointsIdsList, QuantizationSearchParamsBuilder, QueryPointsBuilder, RetrievedPoint,
+        ScoredPoint, ScrollPointsBuilder, SearchParamsBuilder, UpsertPointsBuilder, Value,
+        r#match::MatchValue, point_id::PointIdOptions,
     },
 };
-use serde::Serialize;
+use serde::{Serialize, de::DeserializeOwned};
+use std::collections::HashMap;
+
+/// Builds a Qdrant point id from its string form. Unsigned integers become
+/// numeric ids, anything else is sent as a UUID.
+pub fn to_point_id(id: &str) -> PointId {
+    match id.parse::<u64>() {
+        Ok(num) => num.into(),
+        Err(_) => id.to_string().into(),
+    }
+}
+
+/// Inverse of [`to_point_id`].
+pub fn point_id_to_string(id: Option<PointId>) -> Result<String> {
+    match id.and_then(|id| id.point_id_options) {
+        Some(PointIdOptions::Uuid(uuid)) => Ok(uuid),
+        Some(PointIdOptions::Num(num)) => Ok(num.to_string()),
+        None => Err(Error::PointIdError("point has no id".to_string())),
+    }
+}
+
+pub(crate) fn payload_to_json(payload: HashMap<String, Value>) -> serde_json::Value {
+    serde_json::Value::Object(
+        payload
+            .into_iter()
+            .map(|(key, value)| (key, value.into_json()))
+            .collect(),
+    )
+}
+
+impl<T: DeserializeOwned> TryFrom<RetrievedPoint> for ImageInfo<T> {
+    type Error = Error;
+
+    fn try_from(point: RetrievedPoint) -> Result<Self> {
+        let id = point_id_to_string(point.id)?;
+        ImageInfo::from_payload(id, payload_to_json(point.payload))
+    }
+}
+
+impl<T: DeserializeOwned> TryFrom<ScoredPoint> for ImageInfo<T> {
+    type Error = Error;
+
+    fn try_from(point: ScoredPoint) -> Result<Self> {
+        let id = point_id_to_string(point.id)?;
+        ImageInfo::from_payload(id, payload_to_json(point.payload))
+    }
+}
 
 pub async fn add<T: Serialize>(
     client: &Qdrant,
@@ -29,7 +78,7 @@ pub async fn add<T: Serialize>(
             let json_val = serde_json::to_value(image_info)?;
             let payload = Payload::try_from(json_val)
                 .map_err(|e| Error::JsonToPayloadError(e.to_string()))?;
-            let point = PointStruct::new(image_info.id().to_string(), data.to_vec(), payload);
+            let point = PointStruct::new(to_point_id(image_info.id()), data.to_vec(), payload);
             Ok(point)
         })
         .collect::<Result<Vec<_>>>()?;
@@ -46,7 +95,7 @@ pub async fn add<T: Serialize>(
 }
 
 pub async fn delete_by_ids(client: &Qdrant, collection: &str, ids: &[String]) -> Result<()> {
-    let point_ids = ids.iter().map(|id| id.clone().into()).collect::<Vec<_>>();
+    let point_ids = ids.iter().map(|id| to_point_id(id)).collect::<Vec<_>>();
     let res = client
         .delete_points(
             DeletePointsBuilder::new(collection)
@@ -67,10 +116,19 @@ pub async fn delete_by_extras<T: Into<MatchValue>>(
     collection: &str,
     extra: T,
 ) -> Result<()> {
+    delete_by_filter(
+        client,
+        collection,
+        Filter::must([Condition::matches("extra", extra.into())]),
+    )
+    .await
+}
+
+pub async fn delete_by_filter(client: &Qdrant, collection: &str, filter: Filter) -> Result<()> {
     let response = client
         .delete_points(
             DeletePointsBuilder::new(collection)
-                .points(Filter::must([Condition::matches("extra", extra.into())]))
+                .points(filter)
                 .wait(true),
         )
         .await
@@ -88,10 +146,7 @@ pub async fn search_by_ids(
     with_payload: bool,
     with_vectors: bool,
 ) -> Result<Vec<RetrievedPoint>> {
-    let point_ids = ids
-        .iter()
-        .map(|id| id.to_string().into())
-        .collect::<Vec<_>>();
+    let point_ids = ids.iter().map(|id| to_point_id(id)).collect::<Vec<_>>();
 
     let response = client
         .get_points(
@@ -104,6 +159,68 @@ pub async fn search_by_ids(
     Ok(response.result)
 }
 
+pub async fn get_image_info<T: DeserializeOwned>(
+    client: &Qdrant,
+    collection: &str,
+    ids: &[&str],
+) -> Result<Vec<ImageInfo<T>>> {
+    search_by_ids(client, collection, ids, true, false)
+        .await?
+        .into_iter()
+        .map(ImageInfo::try_from)
+        .collect()
+}
+
+pub async fn scroll(
+    client: &Qdrant,
+    collection: &str,
+    offset: Option<&str>,
+    limit: usize,
+    with_payload: bool,
+    with_vectors: bool,
+) -> Result<(Vec<RetrievedPoint>, Option<String>)> {
+    scroll_filtered(
+        client,
+        collection,
+        offset,
+        limit,
+        None,
+        with_payload,
+        with_vectors,
+    )
+    .await
+}
+
+pub async fn scroll_filtered(
+    client: &Qdrant,
+    collection: &str,
+    offset: Option<&str>,
+    limit: usize,
+    filter: Option<Filter>,
+    with_payload: bool,
+    with_vectors: bool,
+) -> Result<(Vec<RetrievedPoint>, Option<String>)> {
+    let mut builder = ScrollPointsBuilder::new(collection)
+        .limit(limit as u32)
+        .with_payload(with_payload)
+        .with_vectors(with_vectors);
+    if let Some(offset) = offset {
+        builder = builder.offset(to_point_id(offset));
+    }
+    if let Some(filter) = filter {
+        builder = builder.filter(filter);
+    }
+    let response = client
+        .scroll(builder)
+        .await
+        .map_err(|e| Error::SearchPointsError(e.to_string()))?;
+    let next_offset = response
+        .next_page_offset
+        .map(|id| point_id_to_string(Some(id)))
+        .transpose()?;
+    Ok((response.result, next_offset))
+}
+
 pub async fn similarity_search(
     client: &Qdrant,
     collection: &str,
@@ -112,15 +229,116 @@ pub async fn similarity_search(
     with_payload: bool,
     with_vectors: bool,
 ) -> Result<Vec<ScoredPoint>> {
+    similarity_search_with(
+        client,
+        collection,
+        feature,
+        &SearchOptions::new(k),
+        with_payload,
+        with_vectors,
+    )
+    .await
+}
+
+/// Runs `options` as a Qdrant query, its index settings go to the request's
+/// `SearchParams`.
+pub async fn similarity_search_with(
+    client: &Qdrant,
+    collection: &str,
+    feature: &[f32],
+    options: &SearchOptions,
+    with_payload: bool,
+    with_vectors: bool,
+) -> Result<Vec<ScoredPoint>> {
+    let mut query = QueryPointsBuilder::new(collection)
+        .query(feature.to_vec())
+        .limit(options.limit() as u64)
+        .offset(options.offset() as u64)
+        .params(search_params(options))
+        .with_payload(with_payload)
+        .with_vectors(with_vectors);
+    if let Some(threshold) = options.score_threshold() {
+        query = query.score_threshold(threshold);
+    }
+    if let Some(filter) = options.filter() {
+        query = query.filter(filter.to_qdrant());
+    }
     let response = client
-        .query(
-            QueryPointsBuilder::new(collection)
-                .query(feature.to_vec())
-                .limit(k as u64)
-                .with_payload(with_payload)
-                .with_vectors(with_vectors),
-        )
+        .query(query)
         .await
         .map_err(|e| Error::SearchPointsError(e.to_string()))?;
     Ok(response.result)
 }
+
+fn search_params(options: &SearchOptions) -> SearchParamsBuilder {
+    let mut params = SearchParamsBuilder::default().exact(options.exact());
+    if let Some(ef) = options.hnsw_ef() {
+        params = params.hnsw_ef(ef as u64);
+    }
+    if let Some(quantization) = options.quantization() {
+        let mut search = QuantizationSearchParamsBuilder::default().ignore(quantization.ignore());
+        if let Some(rescore) = quantization.rescore() {
+            search = search.rescore(rescore);
+        }
+        if let Some(oversampling) = quantization.oversampling() {
+            search = search.oversampling(oversampling);
+        }
+        params = params.quantization(search);
+    }
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Extra {
+        label: String,
+    }
+
+    fn retrieved(id: PointId, payload: serde_json::Value) -> RetrievedPoint {
+        RetrievedPoint {
+            id: Some(id),
+            payload: Payload::try_from(payload).unwrap().into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_uuid_id_round_trip() {
+        let uuid = "1b4e28ba-2fa1-11d2-883f-0016d3cca427";
+        let point = retrieved(to_point_id(uuid), serde_json::json!({"path": "a.png"}));
+        let info = ImageInfo::<()>::try_from(point).unwrap();
+        assert_eq!(info.id(), uuid);
+        assert_eq!(info.path(), "a.png");
+        assert!(info.extra().is_none());
+    }
+
+    #[test]
+    fn test_numeric_id_round_trip() {
+        let point = retrieved(
+            to_point_id("42"),
+            serde_json::json!({"path": "b.png", "extra": {"label": "cat"}}),
+        );
+        let info = ImageInfo::<Extra>::try_from(point).unwrap();
+        assert_eq!(info.id(), "42");
+        assert_eq!(
+            info.extra(),
+            Some(&Extra {
+                label: "cat".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_payload_mismatch() {
+        let point = retrieved(
+            to_point_id("7"),
+            serde_json::json!({"path": "c.png", "extra": {"label": 1}}),
+        );
+        let err = ImageInfo::<Extra>::try_from(point).unwrap_err();
+        assert!(matches!(err, Error::PayloadError(msg) if msg.contains("point 7")));
+    }
+}
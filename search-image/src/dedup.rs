@@ -0,0 +1,333 @@
+use crate::app::ImageInfo;
+use image::{DynamicImage, imageops::FilterType};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Perceptual hashes of an image, stored in the payload next to its feature.
+///
+/// Unlike the network embedding, these change very little when an image is
+/// re-encoded, resized or slightly recoloured, so a small Hamming distance
+/// means "the same picture" rather than "a similar picture".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ImageHash {
+    /// DCT-based hash of the low frequencies.
+    #[serde(with = "hex_u64")]
+    pub phash: u64,
+    /// Gradient hash comparing horizontally adjacent pixels.
+    #[serde(with = "hex_u64")]
+    pub dhash: u64,
+}
+
+impl ImageHash {
+    pub fn compute(image: &DynamicImage) -> Self {
+        Self {
+            phash: phash(image),
+            dhash: dhash(image),
+        }
+    }
+
+    /// The larger of the pHash and dHash Hamming distances, so both hashes
+    /// have to agree for two images to be close. Ranges from 0 to 64.
+    pub fn distance(&self, other: &Self) -> u32 {
+        (self.phash ^ other.phash)
+            .count_ones()
+            .max((self.dhash ^ other.dhash).count_ones())
+    }
+}
+
+fn phash(image: &DynamicImage) -> u64 {
+    const SIZE: usize = 32;
+    const LOW: usize = 8;
+    let gray = image
+        .resize_exact(SIZE as u32, SIZE as u32, FilterType::Triangle)
+        .into_luma8();
+    let pixels = gray.as_raw().iter().map(|&p| p as f64).collect::<Vec<_>>();
+
+    let basis = |k: usize, n: usize| {
+        (std::f64::consts::PI * k as f64 * (2 * n + 1) as f64 / (2 * SIZE) as f64).cos()
+    };
+    // Separable DCT-II, keeping only the `LOW` lowest frequencies per axis.
+    let mut rows = [[0.0; LOW]; SIZE];
+    for (y, row) in rows.iter_mut().enumerate() {
+        for (u, value) in row.iter_mut().enumerate() {
+            *value = (0..SIZE).map(|x| pixels[y * SIZE + x] * basis(u, x)).sum();
+        }
+    }
+    let mut coefficients = [0.0; LOW * LOW];
+    for v in 0..LOW {
+        for u in 0..LOW {
+            coefficients[v * LOW + u] = (0..SIZE).map(|y| rows[y][u] * basis(v, y)).sum();
+        }
+    }
+
+    // The DC term only carries the mean brightness, leave it out of the median.
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+    coefficients
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| **c > median)
+        .fold(0, |hash, (bit, _)| hash | 1 << bit)
+}
+
+fn dhash(image: &DynamicImage) -> u64 {
+    let gray = image.resize_exact(9, 8, FilterType::Triangle).into_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            if gray.get_pixel(x, y)[0] < gray.get_pixel(x + 1, y)[0] {
+                hash |= 1 << (y * 8 + x);
+            }
+        }
+    }
+    hash
+}
+
+/// Qdrant payload integers are signed, so hashes are stored as hex strings.
+mod hex_u64 {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{value:016x}"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        u64::from_str_radix(&hex, 16).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A stored image close to a query, see
+/// [`App::find_duplicates`](crate::App::find_duplicates).
+#[derive(Debug, Clone)]
+pub struct Duplicate<T = ()> {
+    pub info: ImageInfo<T>,
+    pub distance: u32,
+}
+
+/// Images that are transitively within the distance threshold of each other.
+#[derive(Debug, Clone)]
+pub struct DuplicateCluster<T = ()> {
+    pub members: Vec<ImageInfo<T>>,
+    /// Largest distance between two linked members.
+    pub max_distance: u32,
+}
+
+/// Outcome of [`App::duplicate_report`](crate::App::duplicate_report).
+#[derive(Debug, Clone)]
+pub struct DuplicateReport<T = ()> {
+    /// Clusters of two or more images, largest first.
+    pub clusters: Vec<DuplicateCluster<T>>,
+    /// Points scanned.
+    pub total: usize,
+    /// Points indexed before hashes were stored, left out of the clusters.
+    pub unhashed: usize,
+}
+
+impl<T> DuplicateReport<T> {
+    /// Images that could be removed while keeping one per cluster.
+    pub fn redundant(&self) -> usize {
+        self.clusters.iter().map(|c| c.members.len() - 1).sum()
+    }
+}
+
+/// Groups `items` whose hashes are within `max_distance`.
+///
+/// Candidates are found through a BK-tree on the pHash distance, which is
+/// a lower bound of [`ImageHash::distance`], so the collection is not
+/// compared pairwise.
+pub(crate) fn cluster<T>(
+    items: Vec<(ImageHash, ImageInfo<T>)>,
+    max_distance: u32,
+) -> Vec<DuplicateCluster<T>> {
+    let mut tree = BkTree::default();
+    for (index, (hash, _)) in items.iter().enumerate() {
+        tree.insert(hash.phash, index);
+    }
+
+    let mut parent = (0..items.len()).collect::<Vec<_>>();
+    fn find(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    let mut links = Vec::new();
+    for (i, (hash, _)) in items.iter().enumerate() {
+        for j in tree.within(hash.phash, max_distance) {
+            let distance = hash.distance(&items[j].0);
+            if j > i && distance <= max_distance {
+                let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+                parent[a] = b;
+                links.push((i, distance));
+            }
+        }
+    }
+
+    let mut groups = std::collections::BTreeMap::<usize, (Vec<usize>, u32)>::new();
+    for i in 0..items.len() {
+        let root = find(&mut parent, i);
+        groups.entry(root).or_default().0.push(i);
+    }
+    for (i, distance) in links {
+        let root = find(&mut parent, i);
+        if let Some(group) = groups.get_mut(&root) {
+            group.1 = group.1.max(distance);
+        }
+    }
+
+    let mut slots = items
+        .into_iter()
+        .map(|(_, info)| Some(info))
+        .collect::<Vec<_>>();
+    let mut clusters = groups
+        .into_values()
+        .filter(|(members, _)| members.len() > 1)
+        .map(|(members, max_distance)| DuplicateCluster {
+            members: members
+                .into_iter()
+                .filter_map(|i| slots[i].take())
+                .collect(),
+            max_distance,
+        })
+        .collect::<Vec<_>>();
+    clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.members.len()));
+    clusters
+}
+
+/// Burkhard-Keller tree over 64-bit hashes with the Hamming metric.
+#[derive(Default)]
+struct BkTree {
+    nodes: Vec<BkNode>,
+}
+
+struct BkNode {
+    hash: u64,
+    index: usize,
+    children: Vec<(u32, usize)>,
+}
+
+impl BkTree {
+    fn insert(&mut self, hash: u64, index: usize) {
+        let node = BkNode {
+            hash,
+            index,
+            children: Vec::new(),
+        };
+        if self.nodes.is_empty() {
+            self.nodes.push(node);
+            return;
+        }
+        let mut current = 0;
+        loop {
+            let distance = (self.nodes[current].hash ^ hash).count_ones();
+            match self.nodes[current]
+                .children
+                .iter()
+                .find(|(d, _)| *d == distance)
+            {
+                Some(&(_, child)) => current = child,
+                None => {
+                    let id = self.nodes.len();
+                    self.nodes[current].children.push((distance, id));
+                    self.nodes.push(node);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn within(&self, hash: u64, max_distance: u32) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut stack = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![0]
+        };
+        while let Some(current) = stack.pop() {
+            let node = &self.nodes[current];
+            let distance = (node.hash ^ hash).count_ones();
+            if distance <= max_distance {
+                found.push(node.index);
+            }
+            stack.extend(
+                node.children
+                    .iter()
+                    .filter(|(d, _)| d.abs_diff(distance) <= max_distance)
+                    .map(|&(_, child)| child),
+            );
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn scene(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            let (u, v) = (x as f32 / width as f32, y as f32 / height as f32);
+            let r = 128.0 + 100.0 * (u * 7.0).sin() * (v * 3.0).cos();
+            let g = 255.0 * u * v;
+            let b = if (u - 0.3).powi(2) + (v - 0.6).powi(2) < 0.05 {
+                230.0
+            } else {
+                40.0
+            };
+            Rgb([r as u8, g as u8, b as u8])
+        }))
+    }
+
+    fn checker(size: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(size, size, |x, y| {
+            if (x / 16 + y / 16) % 2 == 0 {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        }))
+    }
+
+    #[test]
+    fn test_resized_copy_is_close() {
+        let original = ImageHash::compute(&scene(256, 192));
+        let resized = ImageHash::compute(&scene(128, 96));
+        let other = ImageHash::compute(&checker(256));
+        assert!(original.distance(&resized) <= 4);
+        assert!(original.distance(&other) > 16);
+    }
+
+    #[test]
+    fn test_serde_roundtrip() {
+        let hash = ImageHash {
+            phash: u64::MAX,
+            dhash: 1,
+        };
+        let json = serde_json::to_value(hash).unwrap();
+        assert_eq!(json["phash"], "ffffffffffffffff");
+        assert_eq!(serde_json::from_value::<ImageHash>(json).unwrap(), hash);
+    }
+
+    #[test]
+    fn test_cluster() {
+        let hash = |phash, dhash| ImageHash { phash, dhash };
+        let items = vec![
+            (hash(0b0000, 0), ImageInfo::new("a", "a.png", None)),
+            (hash(0b0001, 0), ImageInfo::new("b", "b.png", None)),
+            (hash(0b0011, 1), ImageInfo::new("c", "c.png", None)),
+            (hash(u64::MAX, 0), ImageInfo::new("d", "d.png", None)),
+        ];
+        let clusters = cluster::<()>(items, 1);
+        assert_eq!(clusters.len(), 1);
+        let ids = clusters[0]
+            .members
+            .iter()
+            .map(|m| m.id())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["a", "b", "c"]);
+        assert_eq!(clusters[0].max_distance, 1);
+    }
+}
@@ -4,6 +4,8 @@ pub enum Error {
     CUDAError,
     #[error("Metal not available")]
     MetalError,
+    #[error("Model weights not found: {0}")]
+    WeightsNotFound(String),
     #[error("HuggingFace API Error: {0}")]
     HuggingFaceApiError(#[from] hf_hub::api::tokio::ApiError),
     #[error("Candle Error: {0}")]
@@ -18,6 +20,8 @@ pub enum Error {
     FolderNotFound(String),
     #[error("Folder is empty: {0}")]
     FolderEmpty(String),
+    #[error("Invalid Pattern: {0}")]
+    InvalidPattern(String),
     #[error("Collection Error: {0}")]
     CollectionError(String),
     #[error("Serde Error: {0}")]
@@ -30,6 +34,70 @@ pub enum Error {
     DeletePointsError(String),
     #[error("Search Points Error: {0}")]
     SearchPointsError(String),
+    #[error("Payload Error: {0}")]
+    PayloadError(String),
+    #[error("Point Id Error: {0}")]
+    PointIdError(String),
+    #[error("Index Error: {0}")]
+    IndexError(String),
+    #[error("Manifest Error: {0}")]
+    ManifestError(String),
+    #[error("Watch Error: {0}")]
+    WatchError(String),
+    #[error("Tokenizer Error: {0}")]
+    TokenizerError(String),
+    #[error("Text search not supported: {0}")]
+    TextNotSupported(String),
+    #[error("Model Error: {0}")]
+    ModelError(String),
+    #[error("Schema Mismatch: {0}")]
+    SchemaMismatch(crate::schema::SchemaMismatch),
+    #[error("Unrecorded Collection: {0}")]
+    UnrecordedCollection(String),
+    #[error("Config Error: {0}")]
+    ConfigError(String),
+    #[error("Migration Error: {0}")]
+    MigrationError(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+/// Why a single input of a batch could not be turned into a feature. Unlike
+/// [`Error`], these never abort the rest of the batch.
+#[derive(Debug, thiserror::Error)]
+pub enum ItemError {
+    #[error("Decode Error: {0}")]
+    Decode(image::ImageError),
+    #[error("Unsupported Format: {0}")]
+    UnsupportedFormat(image::ImageError),
+    #[error("IO Error: {0}")]
+    Io(std::io::Error),
+}
+
+impl ItemError {
+    /// Splits per-item failures from errors that concern the whole batch,
+    /// which are handed back unchanged.
+    pub fn classify(error: Error) -> std::result::Result<Self, Error> {
+        match error {
+            Error::IOError(e) => Ok(Self::Io(e)),
+            Error::ImageError(image::ImageError::IoError(e)) => Ok(Self::Io(e)),
+            Error::ImageError(e @ image::ImageError::Unsupported(_)) => {
+                Ok(Self::UnsupportedFormat(e))
+            }
+            Error::ImageError(e) => Ok(Self::Decode(e)),
+            e => Err(e),
+        }
+    }
+}
+
+impl From<ItemError> for Error {
+    fn from(error: ItemError) -> Self {
+        match error {
+            ItemError::Decode(e) | ItemError::UnsupportedFormat(e) => Error::ImageError(e),
+            ItemError::Io(e) => Error::IOError(e),
+        }
+    }
+}
+
+/// Outcome of one input of a fault-tolerant batch.
+pub type ItemResult<T = Vec<f32>> = std::result::Result<T, ItemError>;
@@ -1,117 +1,516 @@
 use crate::{
-    config::NetworkKind,
-    error::{Error, Result},
-    utils::image_to_tensor,
+    config::{MobilenetConfig, NetworkKind},
+    dedup::ImageHash,
+    error::{Error, ItemError, ItemResult, Result},
+    model::{Clip, ConvNext, FeatureModel, MobileNetV4},
+    preprocess::Preprocessor,
+    scan::FolderScan,
+    utils::{decode_image, load_image},
 };
 use candle_core::{DType, Device, Tensor};
-use candle_nn::{Module, VarBuilder};
-use candle_transformers::models::{mimi::candle_nn::Func, mobilenetv4};
+use candle_nn::VarBuilder;
+use candle_transformers::models::mobilenetv4;
+use image::DynamicImage;
+use std::sync::Arc;
+use tokenizers::Tokenizer;
 
+/// Length of the features of the MobileNetV4 networks.
+#[deprecated(note = "the size depends on the model, use `Extractor::feature_size`")]
 pub const FEATURE_SIZE: usize = 960;
 
+/// A feature with the perceptual hash of the same image.
+pub type HashedFeature = (Vec<f32>, ImageHash);
+
+/// A feature with whatever else was derived while decoding its input.
+type WithSide<H> = (Vec<f32>, H);
+
+/// One chunk of a chunked extraction: every input with its outcome.
+pub type Chunk<'a, I, T = Vec<f32>> = Result<Vec<(&'a I, ItemResult<T>)>>;
+
 #[derive(Debug, Clone)]
 pub struct Extractor {
-    kind: NetworkKind,
-    network: Func<'static>,
+    model: Arc<dyn FeatureModel>,
+    network: Option<NetworkKind>,
     device: Device,
+    preprocessor: Preprocessor,
+    max_batch_size: usize,
 }
 
+const WEIGHTS_FILE: &str = "model.safetensors";
+const TOKENIZER_FILE: &str = "tokenizer.json";
+
 impl Extractor {
     pub async fn new(kind: NetworkKind, device: &Device) -> Result<Self> {
-        let config = kind.config();
-        let model_name = kind.model_filename();
-        let api = hf_hub::api::tokio::ApiBuilder::new()
-            .with_cache_dir(std::path::PathBuf::from("./.cache"))
-            .build()?;
-        let api = api.model(model_name);
-        let model_file = api.get("model.safetensors").await?;
+        Self::with_config(&MobilenetConfig::new(kind, Default::default()), device).await
+    }
+
+    /// Builds the extractor from `config`, honouring its explicit weights
+    /// path, cache directory and offline mode. The device in `config` is
+    /// ignored in favour of `device`.
+    pub async fn with_config(config: &MobilenetConfig, device: &Device) -> Result<Self> {
+        let kind = config.kind();
+        let model_file = resolve_file(config, config.weights(), WEIGHTS_FILE).await?;
         let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[model_file], DType::F32, device)? };
-        let network = mobilenetv4::mobilenetv4_no_final_layer(&config, vb)?;
+        let model: Arc<dyn FeatureModel> = match kind {
+            NetworkKind::Clip => {
+                let tokenizer_file =
+                    resolve_file(config, config.tokenizer(), TOKENIZER_FILE).await?;
+                let tokenizer = Tokenizer::from_file(tokenizer_file)
+                    .map_err(|e| Error::TokenizerError(e.to_string()))?;
+                Arc::new(Clip::load(vb, tokenizer)?)
+            }
+            NetworkKind::ConvnextTiny | NetworkKind::ConvnextBase => {
+                Arc::new(ConvNext::load(kind, vb)?)
+            }
+            _ => Arc::new(MobileNetV4::load(kind, vb)?),
+        };
+        let preprocessor = model.preprocessor().with_config(config.preprocess());
         Ok(Self {
-            kind,
-            network,
-            device: device.clone(),
+            network: Some(kind),
+            ..Self::from_model(model, device)
+                .with_preprocessor(preprocessor)
+                .with_max_batch_size(config.max_batch_size())
         })
     }
 
+    /// Wraps an already loaded `model`, with its default preprocessing.
+    pub fn from_model(model: Arc<dyn FeatureModel>, device: &Device) -> Self {
+        Self {
+            preprocessor: model.preprocessor(),
+            model,
+            network: None,
+            device: device.clone(),
+            max_batch_size: MobilenetConfig::default().max_batch_size(),
+        }
+    }
+
+    /// Caps how many images are decoded and forwarded together, which bounds
+    /// memory use for large batches.
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size.max(1);
+        self
+    }
+
+    pub fn max_batch_size(&self) -> usize {
+        self.max_batch_size
+    }
+
+    /// Replaces the preprocessing pipeline. Features extracted with different
+    /// pipelines are not comparable, see [`Preprocessor::version`].
+    pub fn with_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessor = preprocessor;
+        self
+    }
+
+    pub fn preprocessor(&self) -> &Preprocessor {
+        &self.preprocessor
+    }
+
+    /// The built-in network the extractor was loaded from, `None` for a
+    /// model handed to [`from_model`](Self::from_model).
+    pub fn network(&self) -> Option<NetworkKind> {
+        self.network
+    }
+
+    /// # Panics
+    ///
+    /// If the extractor was built with [`from_model`](Self::from_model).
+    #[deprecated(note = "use `network`, or `model().name()` to identify any model")]
     pub fn kind(&self) -> NetworkKind {
-        self.kind
+        self.network
+            .expect("the extractor wraps a model that is not a built-in network")
+    }
+
+    /// The MobileNetV4 architecture, `None` for other models.
+    #[deprecated(note = "use `model` to inspect the loaded model")]
+    pub fn config(&self) -> Option<mobilenetv4::Config> {
+        self.network?.mobilenet_config()
+    }
+
+    pub fn model(&self) -> &dyn FeatureModel {
+        self.model.as_ref()
     }
 
-    pub fn config(&self) -> mobilenetv4::Config {
-        self.kind.config()
+    /// Length of the features this extractor produces, the dimension of the
+    /// collection it feeds.
+    pub fn feature_size(&self) -> usize {
+        self.model.dim()
+    }
+
+    /// Embeds `text` into the same space as the images, for networks where
+    /// [`NetworkKind::supports_text`] holds.
+    pub fn extract_text(&self, text: &str) -> Result<Vec<f32>> {
+        let feature = self.model.encode_text(text)?.flatten_all()?;
+        Ok(feature.to_vec1::<f32>()?)
     }
 
     pub fn extract<T>(&self, image_path: T) -> Result<Vec<f32>>
     where
         T: AsRef<std::path::Path>,
     {
-        let img = image_to_tensor(image_path, Some((self.resolution(), self.resolution())))?
-            .to_device(&self.device)?;
-        let feature = self.network.forward(&img.unsqueeze(0)?)?.flatten_all()?;
+        let img = self.preprocessor.apply(&load_image(image_path)?)?;
+        self.forward_single(&img)
+    }
+
+    pub fn extract_image(&self, image: &DynamicImage) -> Result<Vec<f32>> {
+        let img = self.preprocessor.apply(image)?;
+        self.forward_single(&img)
+    }
+
+    /// Decodes an encoded image (PNG, JPEG, ...) held in memory and extracts
+    /// its feature. The format is guessed from the content.
+    pub fn extract_bytes(&self, bytes: &[u8]) -> Result<Vec<f32>> {
+        self.extract_image(&decode_image(bytes)?)
+    }
+
+    /// Extracts the feature of the image at `image_path` together with its
+    /// perceptual [`ImageHash`], decoding the file once.
+    pub fn extract_with_hash<T>(&self, image_path: T) -> Result<(Vec<f32>, ImageHash)>
+    where
+        T: AsRef<std::path::Path>,
+    {
+        self.extract_image_with_hash(&load_image(image_path)?)
+    }
+
+    pub fn extract_bytes_with_hash(&self, bytes: &[u8]) -> Result<(Vec<f32>, ImageHash)> {
+        self.extract_image_with_hash(&decode_image(bytes)?)
+    }
+
+    pub fn extract_image_with_hash(&self, image: &DynamicImage) -> Result<(Vec<f32>, ImageHash)> {
+        Ok((self.extract_image(image)?, ImageHash::compute(image)))
+    }
+
+    fn forward_single(&self, img: &Tensor) -> Result<Vec<f32>> {
+        let img = img.to_device(&self.device)?;
+        let feature = self.model.forward(&img.unsqueeze(0)?)?.flatten_all()?;
         Ok(feature.to_vec1::<f32>()?)
     }
 
     pub fn extract_batch<T>(&self, image_paths: &[T]) -> Result<Vec<Vec<f32>>>
     where
-        T: AsRef<std::path::Path>,
+        T: AsRef<std::path::Path> + Sync,
+    {
+        self.extract_many(image_paths, |path| {
+            self.preprocessor.apply(&load_image(path)?)
+        })
+    }
+
+    pub fn extract_image_batch(&self, images: &[DynamicImage]) -> Result<Vec<Vec<f32>>> {
+        self.extract_many(images, |image| self.preprocessor.apply(image))
+    }
+
+    pub fn extract_bytes_batch<B>(&self, buffers: &[B]) -> Result<Vec<Vec<f32>>>
+    where
+        B: AsRef<[u8]> + Sync,
+    {
+        self.extract_many(buffers, |bytes| {
+            self.preprocessor.apply(&decode_image(bytes.as_ref())?)
+        })
+    }
+
+    /// Like [`Extractor::extract_batch`], but an input that cannot be read or
+    /// decoded only fails its own entry. The result has one entry per path,
+    /// in order.
+    pub fn try_extract_batch<T>(&self, image_paths: &[T]) -> Result<Vec<ItemResult>>
+    where
+        T: AsRef<std::path::Path> + Sync,
     {
-        let process_image = |path: &T| -> Result<Tensor> {
-            Ok(
-                image_to_tensor(path, Some((self.resolution(), self.resolution())))?
-                    .to_device(&self.device)?,
-            )
+        self.try_extract_many(image_paths, |path| {
+            self.preprocessor.apply(&load_image(path)?)
+        })
+    }
+
+    pub fn try_extract_bytes_batch<B>(&self, buffers: &[B]) -> Result<Vec<ItemResult>>
+    where
+        B: AsRef<[u8]> + Sync,
+    {
+        self.try_extract_many(buffers, |bytes| {
+            self.preprocessor.apply(&decode_image(bytes.as_ref())?)
+        })
+    }
+
+    /// Lazily extracts `image_paths` in chunks of at most
+    /// [`Extractor::max_batch_size`]. Each item of the iterator holds one
+    /// chunk's paths with their outcomes, so callers can store or drop
+    /// features before the next chunk is decoded.
+    pub fn extract_chunks<'a, T>(
+        &'a self,
+        image_paths: &'a [T],
+    ) -> impl Iterator<Item = Chunk<'a, T>> + 'a
+    where
+        T: AsRef<std::path::Path> + Sync,
+    {
+        image_paths.chunks(self.max_batch_size).map(move |chunk| {
+            let results =
+                self.try_extract_chunk(chunk, |path| self.preprocessor.apply(&load_image(path)?))?;
+            Ok(chunk.iter().zip(results).collect())
+        })
+    }
+
+    pub fn extract_bytes_chunks<'a, B>(
+        &'a self,
+        buffers: &'a [B],
+    ) -> impl Iterator<Item = Chunk<'a, B>> + 'a
+    where
+        B: AsRef<[u8]> + Sync,
+    {
+        buffers.chunks(self.max_batch_size).map(move |chunk| {
+            let results = self.try_extract_chunk(chunk, |bytes| {
+                self.preprocessor.apply(&decode_image(bytes.as_ref())?)
+            })?;
+            Ok(chunk.iter().zip(results).collect())
+        })
+    }
+
+    /// Like [`Extractor::extract_chunks`], also computing the [`ImageHash`] of
+    /// every image that could be decoded.
+    pub fn extract_hashed_chunks<'a, T>(
+        &'a self,
+        image_paths: &'a [T],
+    ) -> impl Iterator<Item = Chunk<'a, T, HashedFeature>> + 'a
+    where
+        T: AsRef<std::path::Path> + Sync,
+    {
+        image_paths.chunks(self.max_batch_size).map(move |chunk| {
+            let results =
+                self.try_extract_chunk_with(chunk, |path| self.decode_hashed(&load_image(path)?))?;
+            Ok(chunk.iter().zip(results).collect())
+        })
+    }
+
+    pub fn extract_bytes_hashed_chunks<'a, B>(
+        &'a self,
+        buffers: &'a [B],
+    ) -> impl Iterator<Item = Chunk<'a, B, HashedFeature>> + 'a
+    where
+        B: AsRef<[u8]> + Sync,
+    {
+        buffers.chunks(self.max_batch_size).map(move |chunk| {
+            let results = self.try_extract_chunk_with(chunk, |bytes| {
+                self.decode_hashed(&decode_image(bytes.as_ref())?)
+            })?;
+            Ok(chunk.iter().zip(results).collect())
+        })
+    }
+
+    fn decode_hashed(&self, image: &DynamicImage) -> Result<(Tensor, ImageHash)> {
+        Ok((self.preprocessor.apply(image)?, ImageHash::compute(image)))
+    }
+
+    fn extract_many<I, F>(&self, items: &[I], to_tensor: F) -> Result<Vec<Vec<f32>>>
+    where
+        I: Sync,
+        F: Fn(&I) -> Result<Tensor> + Sync,
+    {
+        self.try_extract_many(items, to_tensor)?
+            .into_iter()
+            .map(|item| item.map_err(Error::from))
+            .collect()
+    }
+
+    fn try_extract_many<I, F>(&self, items: &[I], to_tensor: F) -> Result<Vec<ItemResult>>
+    where
+        I: Sync,
+        F: Fn(&I) -> Result<Tensor> + Sync,
+    {
+        let mut results = Vec::with_capacity(items.len());
+        for chunk in items.chunks(self.max_batch_size) {
+            results.extend(self.try_extract_chunk(chunk, &to_tensor)?);
+        }
+        Ok(results)
+    }
+
+    fn try_extract_chunk<I, F>(&self, items: &[I], to_tensor: F) -> Result<Vec<ItemResult>>
+    where
+        I: Sync,
+        F: Fn(&I) -> Result<Tensor> + Sync,
+    {
+        Ok(self
+            .try_extract_chunk_with(items, |item| Ok((to_tensor(item)?, ())))?
+            .into_iter()
+            .map(|result| result.map(|(feature, ())| feature))
+            .collect())
+    }
+
+    /// Turns every item into a tensor and a side value with `decode`, in
+    /// parallel when the `rayon` feature is on, and runs the tensors that
+    /// succeeded through the network as one batch.
+    fn try_extract_chunk_with<I, H, F>(
+        &self,
+        items: &[I],
+        decode: F,
+    ) -> Result<Vec<ItemResult<WithSide<H>>>>
+    where
+        I: Sync,
+        H: Send,
+        F: Fn(&I) -> Result<(Tensor, H)> + Sync,
+    {
+        let process_image = |item: &I| -> Result<ItemResult<(Tensor, H)>> {
+            let decoded =
+                decode(item).and_then(|(tensor, side)| Ok((tensor.to_device(&self.device)?, side)));
+            match decoded {
+                Ok(decoded) => Ok(Ok(decoded)),
+                Err(e) => ItemError::classify(e).map(Err),
+            }
         };
         cfg_if::cfg_if! {
             if #[cfg(feature = "rayon")] {
                 use rayon::prelude::*;
-                let tensors = image_paths
+                let tensors = items
                     .par_iter()
                     .map(process_image)
                     .collect::<Result<Vec<_>>>()?;
             } else {
-                let tensors = image_paths
+                let tensors = items
                     .iter()
                     .map(process_image)
                     .collect::<Result<Vec<_>>>()?;
             }
         };
 
-        let batch_tensor = Tensor::stack(&tensors, 0)?;
-        let features = self
-            .network
-            .forward(&batch_tensor)?
-            .flatten_from(1)?
-            .to_vec2::<f32>()?;
+        let decoded = tensors
+            .iter()
+            .filter_map(|tensor| tensor.as_ref().ok().map(|(tensor, _)| tensor))
+            .collect::<Vec<_>>();
+        let mut features = if decoded.is_empty() {
+            Vec::new()
+        } else {
+            let batch_tensor = Tensor::stack(&decoded, 0)?;
+            self.model
+                .forward(&batch_tensor)?
+                .flatten_from(1)?
+                .to_vec2::<f32>()?
+        }
+        .into_iter();
 
-        Ok(features)
+        Ok(tensors
+            .into_iter()
+            .map(|tensor| tensor.map(|(_, side)| (features.next().unwrap_or_default(), side)))
+            .collect())
     }
 
+    /// Extracts every image under `folder_path`, recursively, using the
+    /// default [`FolderScan`] rules.
     pub fn extract_folder<T>(&self, folder_path: T) -> Result<Vec<Vec<f32>>>
     where
         T: AsRef<std::path::Path>,
     {
-        let path_str = folder_path.as_ref().to_string_lossy().to_string();
-        if !(folder_path.as_ref().exists() && folder_path.as_ref().is_dir()) {
-            return Err(Error::FolderNotFound(path_str));
-        }
-        let image_paths = std::fs::read_dir(folder_path)?
-            .map(|entry| -> Result<_> { Ok(entry?.path()) })
-            .collect::<Result<Vec<_>>>()?;
-        if image_paths.is_empty() {
-            return Err(Error::FolderEmpty(path_str));
-        }
+        self.extract_folder_with(folder_path, &FolderScan::default())
+    }
+
+    pub fn extract_folder_with<T>(&self, folder_path: T, scan: &FolderScan) -> Result<Vec<Vec<f32>>>
+    where
+        T: AsRef<std::path::Path>,
+    {
+        let image_paths = scan_folder(folder_path.as_ref(), scan)?;
         self.extract_batch(&image_paths)
     }
 
+    /// Fault-tolerant [`Extractor::extract_folder`], returning each file's
+    /// path with its outcome.
+    pub fn try_extract_folder<T>(
+        &self,
+        folder_path: T,
+    ) -> Result<Vec<(std::path::PathBuf, ItemResult)>>
+    where
+        T: AsRef<std::path::Path>,
+    {
+        self.try_extract_folder_with(folder_path, &FolderScan::default())
+    }
+
+    pub fn try_extract_folder_with<T>(
+        &self,
+        folder_path: T,
+        scan: &FolderScan,
+    ) -> Result<Vec<(std::path::PathBuf, ItemResult)>>
+    where
+        T: AsRef<std::path::Path>,
+    {
+        let image_paths = scan_folder(folder_path.as_ref(), scan)?;
+        let results = self.try_extract_batch(&image_paths)?;
+        Ok(image_paths.into_iter().zip(results).collect())
+    }
+
     pub fn resolution(&self) -> u32 {
-        self.kind.resolution()
+        self.model.resolution()
+    }
+}
+
+fn scan_folder(
+    folder_path: &std::path::Path,
+    scan: &FolderScan,
+) -> Result<Vec<std::path::PathBuf>> {
+    let image_paths = scan.scan(folder_path)?;
+    if image_paths.is_empty() {
+        return Err(Error::FolderEmpty(
+            folder_path.to_string_lossy().to_string(),
+        ));
+    }
+    Ok(image_paths)
+}
+
+/// Finds `filename` of the network in `config`: the `explicit` path if set,
+/// otherwise the HuggingFace cache, downloading into it unless offline.
+async fn resolve_file(
+    config: &MobilenetConfig,
+    explicit: Option<&std::path::Path>,
+    filename: &str,
+) -> Result<std::path::PathBuf> {
+    let kind = config.kind();
+    let model_name = kind.model_filename();
+    if let Some(path) = explicit {
+        return if path.is_file() {
+            Ok(path.to_path_buf())
+        } else {
+            Err(Error::WeightsNotFound(format!(
+                "{} does not exist (expected {filename} of {model_name})",
+                path.display()
+            )))
+        };
     }
+
+    let repo = hf_hub::Repo::with_revision(
+        model_name.clone(),
+        hf_hub::RepoType::Model,
+        kind.revision().to_string(),
+    );
+    let cache_dir = config.cache_dir().to_path_buf();
+    if config.offline() {
+        return hf_hub::Cache::new(cache_dir.clone())
+            .repo(repo)
+            .get(filename)
+            .ok_or_else(|| {
+                Error::WeightsNotFound(format!(
+                    "{filename} of {model_name} is not in {} and offline mode is on",
+                    cache_dir.display()
+                ))
+            });
+    }
+
+    let api = hf_hub::api::tokio::ApiBuilder::new()
+        .with_cache_dir(cache_dir)
+        .build()?;
+    Ok(api.repo(repo).get(filename).await?)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::tests::TempDir;
+
+    #[tokio::test]
+    async fn test_offline_cache_miss() {
+        let cache_dir = TempDir::new("empty-cache");
+        let config = MobilenetConfig::new(NetworkKind::Small, Default::default())
+            .with_cache_dir(cache_dir.to_path_buf())
+            .with_offline(true);
+        let err = Extractor::with_config(&config, &Device::Cpu)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::WeightsNotFound(msg) if msg.contains("mobilenetv4_conv_small"))
+        );
+    }
 
     #[tokio::test]
     async fn test_small() {
@@ -119,7 +518,53 @@ mod tests {
             .await
             .unwrap();
         let feature = extractor.extract("data/cpp.png").unwrap();
-        assert_eq!(feature.len(), FEATURE_SIZE);
+        assert_eq!(feature.len(), extractor.feature_size());
+    }
+
+    #[tokio::test]
+    async fn test_small_bytes() {
+        let extractor = Extractor::new(NetworkKind::Small, &Device::Cpu)
+            .await
+            .unwrap();
+        let bytes = std::fs::read("data/cpp.png").unwrap();
+        let from_bytes = extractor.extract_bytes(&bytes).unwrap();
+        let from_path = extractor.extract("data/cpp.png").unwrap();
+        assert_eq!(from_bytes, from_path);
+        let batch = extractor.extract_bytes_batch(&[&bytes, &bytes]).unwrap();
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch[0].len(), extractor.feature_size());
+    }
+
+    #[tokio::test]
+    async fn test_small_try_batch() {
+        let extractor = Extractor::new(NetworkKind::Small, &Device::Cpu)
+            .await
+            .unwrap();
+        let results = extractor
+            .try_extract_batch(&["data/cpp.png", "Cargo.toml", "data/missing.png"])
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().len(), extractor.feature_size());
+        assert!(matches!(results[1], Err(ItemError::UnsupportedFormat(_))));
+        assert!(matches!(results[2], Err(ItemError::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn test_small_chunks() {
+        let extractor = Extractor::new(NetworkKind::Small, &Device::Cpu)
+            .await
+            .unwrap()
+            .with_max_batch_size(2);
+        let paths = ["data/cpp.png"; 5];
+        let chunks = extractor
+            .extract_chunks(&paths)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            chunks.iter().map(|chunk| chunk.len()).collect::<Vec<_>>(),
+            [2, 2, 1]
+        );
+        assert_eq!(extractor.extract_batch(&paths).unwrap().len(), 5);
     }
 
     #[tokio::test]
@@ -128,7 +573,7 @@ mod tests {
             .await
             .unwrap();
         let feature = extractor.extract("data/cpp.png").unwrap();
-        assert_eq!(feature.len(), FEATURE_SIZE);
+        assert_eq!(feature.len(), extractor.feature_size());
     }
 
     #[tokio::test]
@@ -137,7 +582,7 @@ mod tests {
             .await
             .unwrap();
         let feature = extractor.extract("data/cpp.png").unwrap();
-        assert_eq!(feature.len(), FEATURE_SIZE);
+        assert_eq!(feature.len(), extractor.feature_size());
     }
 
     #[tokio::test]
@@ -146,7 +591,7 @@ mod tests {
             .await
             .unwrap();
         let feature = extractor.extract("data/cpp.png").unwrap();
-        assert_eq!(feature.len(), FEATURE_SIZE);
+        assert_eq!(feature.len(), extractor.feature_size());
     }
 
     #[tokio::test]
@@ -155,6 +600,36 @@ mod tests {
             .await
             .unwrap();
         let feature = extractor.extract("data/cpp.png").unwrap();
-        assert_eq!(feature.len(), FEATURE_SIZE);
+        assert_eq!(feature.len(), extractor.feature_size());
+    }
+
+    #[tokio::test]
+    async fn test_clip_text() {
+        let extractor = Extractor::new(NetworkKind::Clip, &Device::Cpu)
+            .await
+            .unwrap();
+        let image = extractor.extract("data/cpp.png").unwrap();
+        let text = extractor.extract_text("a logo").unwrap();
+        assert_eq!(image.len(), extractor.feature_size());
+        assert_eq!(text.len(), image.len());
+    }
+
+    #[tokio::test]
+    async fn test_text_not_supported() {
+        let extractor = Extractor::new(NetworkKind::Small, &Device::Cpu)
+            .await
+            .unwrap();
+        let err = extractor.extract_text("a logo").unwrap_err();
+        assert!(matches!(err, Error::TextNotSupported(_)));
+    }
+
+    #[tokio::test]
+    async fn test_convnext_tiny() {
+        let extractor = Extractor::new(NetworkKind::ConvnextTiny, &Device::Cpu)
+            .await
+            .unwrap();
+        let feature = extractor.extract("data/cpp.png").unwrap();
+        assert_eq!(extractor.feature_size(), 768);
+        assert_eq!(feature.len(), extractor.feature_size());
     }
 }
@@ -0,0 +1,520 @@
+use crate::error::{Error, Result};
+use qdrant_client::qdrant::{self, r#match::MatchValue};
+use serde_json::Value;
+
+/// Conditions on point payloads, for [`SearchOptions::with_filter`],
+/// [`VectorStore::scroll_with`] and [`VectorStore::delete_where`].
+///
+/// Keys are dotted paths into the stored [`ImageInfo`] payload, such as
+/// `path` or `extra.camera.model`, see [`extra`]. A key that reaches an
+/// array matches if any of its elements does. A point matches if every
+/// `must` condition holds, at least one `should` condition holds (when there
+/// are any) and no `must_not` condition does. The empty filter matches every
+/// point.
+///
+/// [`SearchOptions::with_filter`]: crate::store::SearchOptions::with_filter
+/// [`VectorStore::scroll_with`]: crate::store::VectorStore::scroll_with
+/// [`VectorStore::delete_where`]: crate::store::VectorStore::delete_where
+/// [`ImageInfo`]: crate::ImageInfo
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PayloadFilter {
+    must: Vec<Condition>,
+    should: Vec<Condition>,
+    must_not: Vec<Condition>,
+}
+
+impl PayloadFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn must(mut self, condition: impl Into<Condition>) -> Self {
+        self.must.push(condition.into());
+        self
+    }
+
+    pub fn should(mut self, condition: impl Into<Condition>) -> Self {
+        self.should.push(condition.into());
+        self
+    }
+
+    pub fn must_not(mut self, condition: impl Into<Condition>) -> Self {
+        self.must_not.push(condition.into());
+        self
+    }
+
+    /// Whether the filter matches every point: it has no conditions, or only
+    /// `must` and `should` conditions that are empty filters themselves.
+    pub fn is_empty(&self) -> bool {
+        let empty = |c: &Condition| matches!(c, Condition::Filter(filter) if filter.is_empty());
+        self.must.iter().all(empty) && self.should.iter().all(empty) && self.must_not.is_empty()
+    }
+
+    /// Refuses the empty filter for [`VectorStore::delete_where`], where it
+    /// would wipe the collection.
+    ///
+    /// [`VectorStore::delete_where`]: crate::store::VectorStore::delete_where
+    pub(crate) fn check_deletable(&self) -> Result<()> {
+        if self.is_empty() {
+            return Err(Error::DeletePointsError(
+                "refusing to delete with an empty filter, which matches every point".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Evaluates the filter against a stored payload, the way Qdrant would.
+    pub fn matches(&self, payload: &Value) -> bool {
+        self.must.iter().all(|c| c.holds(payload))
+            && (self.should.is_empty() || self.should.iter().any(|c| c.holds(payload)))
+            && !self.must_not.iter().any(|c| c.holds(payload))
+    }
+
+    pub fn to_qdrant(&self) -> qdrant::Filter {
+        let convert = |conditions: &[Condition]| {
+            conditions
+                .iter()
+                .map(Condition::to_qdrant)
+                .collect::<Vec<_>>()
+        };
+        qdrant::Filter {
+            must: convert(&self.must),
+            should: convert(&self.should),
+            must_not: convert(&self.must_not),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&PayloadFilter> for qdrant::Filter {
+    fn from(filter: &PayloadFilter) -> Self {
+        filter.to_qdrant()
+    }
+}
+
+/// The payload key of `field` inside the `extra` data of an image.
+pub fn extra(field: &str) -> String {
+    format!("extra.{field}")
+}
+
+/// One condition of a [`PayloadFilter`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// The value at `key` equals `value`.
+    Match { key: String, value: FieldValue },
+    /// The value at `key` is one of `values`.
+    Any { key: String, values: FieldValues },
+    /// The value at `key` is none of `values`. Points without the key do not
+    /// match, combine with [`Condition::IsEmpty`] in a `should` to keep them.
+    Except { key: String, values: FieldValues },
+    /// The number at `key` lies in `range`.
+    Range { key: String, range: Range },
+    /// `key` is missing, `null` or an empty array.
+    IsEmpty { key: String },
+    /// The image lies below the directory, compared by whole path components
+    /// against the ancestors stored in the `dirs` payload field. Points
+    /// written before that field existed never match.
+    PathPrefix(String),
+    /// A nested filter, to combine `should` groups under `must` and so on.
+    Filter(PayloadFilter),
+}
+
+impl Condition {
+    pub fn matches(key: impl Into<String>, value: impl Into<FieldValue>) -> Self {
+        Self::Match {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+
+    pub fn any(key: impl Into<String>, values: impl Into<FieldValues>) -> Self {
+        Self::Any {
+            key: key.into(),
+            values: values.into(),
+        }
+    }
+
+    pub fn except(key: impl Into<String>, values: impl Into<FieldValues>) -> Self {
+        Self::Except {
+            key: key.into(),
+            values: values.into(),
+        }
+    }
+
+    pub fn range(key: impl Into<String>, range: Range) -> Self {
+        Self::Range {
+            key: key.into(),
+            range,
+        }
+    }
+
+    pub fn is_empty(key: impl Into<String>) -> Self {
+        Self::IsEmpty { key: key.into() }
+    }
+
+    pub fn path_prefix(prefix: impl Into<String>) -> Self {
+        Self::PathPrefix(prefix.into())
+    }
+
+    fn holds(&self, payload: &Value) -> bool {
+        match self {
+            Self::Match { key, value } => lookup(payload, key).any(|found| value.eq_json(found)),
+            Self::Any { key, values } => lookup(payload, key).any(|found| values.contains(found)),
+            Self::Except { key, values } => {
+                lookup(payload, key).any(|found| !values.contains(found))
+            }
+            Self::Range { key, range } => lookup(payload, key)
+                .filter_map(Value::as_f64)
+                .any(|found| range.contains(found)),
+            Self::IsEmpty { key } => lookup(payload, key).next().is_none(),
+            Self::PathPrefix(prefix) => {
+                let dir = dir_key(prefix);
+                lookup(payload, "dirs").any(|found| found.as_str() == Some(dir))
+            }
+            Self::Filter(filter) => filter.matches(payload),
+        }
+    }
+
+    fn to_qdrant(&self) -> qdrant::Condition {
+        match self {
+            Self::Match { key, value } => qdrant::Condition::matches(key, value.to_match()),
+            Self::Any { key, values } => qdrant::Condition::matches(key, values.to_match()),
+            Self::Except { key, values } => {
+                let except = match values {
+                    FieldValues::Keywords(strings) => {
+                        MatchValue::ExceptKeywords(qdrant::RepeatedStrings {
+                            strings: strings.clone(),
+                        })
+                    }
+                    FieldValues::Integers(integers) => {
+                        MatchValue::ExceptIntegers(qdrant::RepeatedIntegers {
+                            integers: integers.clone(),
+                        })
+                    }
+                };
+                qdrant::Condition::matches(key, except)
+            }
+            Self::Range { key, range } => qdrant::Condition::range(
+                key,
+                qdrant::Range {
+                    lt: range.lt,
+                    gt: range.gt,
+                    gte: range.gte,
+                    lte: range.lte,
+                },
+            ),
+            Self::IsEmpty { key } => qdrant::Condition::is_empty(key),
+            Self::PathPrefix(prefix) => {
+                qdrant::Condition::matches("dirs", MatchValue::Keyword(dir_key(prefix).to_string()))
+            }
+            Self::Filter(filter) => filter.to_qdrant().into(),
+        }
+    }
+}
+
+/// The directories `path` lies in, innermost first, as stored in the `dirs`
+/// payload field for [`Condition::PathPrefix`].
+pub(crate) fn path_dirs(path: &str) -> Vec<String> {
+    std::path::Path::new(path)
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.to_string_lossy().to_string())
+        .filter(|dir| !dir.is_empty())
+        .collect()
+}
+
+/// `prefix` as it appears in [`path_dirs`], without trailing separators.
+fn dir_key(prefix: &str) -> &str {
+    match prefix.trim_end_matches(['/', std::path::MAIN_SEPARATOR]) {
+        "" => &prefix[..prefix.len().min(1)],
+        dir => dir,
+    }
+}
+
+impl From<PayloadFilter> for Condition {
+    fn from(filter: PayloadFilter) -> Self {
+        Self::Filter(filter)
+    }
+}
+
+/// A single value to compare a payload field with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldValue {
+    Keyword(String),
+    Integer(i64),
+    Bool(bool),
+}
+
+impl FieldValue {
+    fn eq_json(&self, value: &Value) -> bool {
+        match self {
+            Self::Keyword(keyword) => value.as_str() == Some(keyword.as_str()),
+            Self::Integer(integer) => value.as_i64() == Some(*integer),
+            Self::Bool(boolean) => value.as_bool() == Some(*boolean),
+        }
+    }
+
+    fn to_match(&self) -> MatchValue {
+        match self {
+            // `MatchValue::from(String)` turns strings with spaces into text
+            // matches, a keyword has to match exactly.
+            Self::Keyword(keyword) => MatchValue::Keyword(keyword.clone()),
+            Self::Integer(integer) => MatchValue::Integer(*integer),
+            Self::Bool(boolean) => MatchValue::Boolean(*boolean),
+        }
+    }
+}
+
+impl From<&str> for FieldValue {
+    fn from(keyword: &str) -> Self {
+        Self::Keyword(keyword.to_string())
+    }
+}
+
+impl From<String> for FieldValue {
+    fn from(keyword: String) -> Self {
+        Self::Keyword(keyword)
+    }
+}
+
+impl From<i64> for FieldValue {
+    fn from(integer: i64) -> Self {
+        Self::Integer(integer)
+    }
+}
+
+impl From<bool> for FieldValue {
+    fn from(boolean: bool) -> Self {
+        Self::Bool(boolean)
+    }
+}
+
+/// A set of values for [`Condition::Any`] and [`Condition::Except`]. Qdrant
+/// only matches sets of keywords or of integers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldValues {
+    Keywords(Vec<String>),
+    Integers(Vec<i64>),
+}
+
+impl FieldValues {
+    fn contains(&self, value: &Value) -> bool {
+        match self {
+            Self::Keywords(keywords) => value
+                .as_str()
+                .is_some_and(|found| keywords.iter().any(|keyword| keyword == found)),
+            Self::Integers(integers) => value
+                .as_i64()
+                .is_some_and(|found| integers.contains(&found)),
+        }
+    }
+
+    fn to_match(&self) -> MatchValue {
+        match self {
+            Self::Keywords(strings) => MatchValue::Keywords(qdrant::RepeatedStrings {
+                strings: strings.clone(),
+            }),
+            Self::Integers(integers) => MatchValue::Integers(qdrant::RepeatedIntegers {
+                integers: integers.clone(),
+            }),
+        }
+    }
+}
+
+impl From<Vec<String>> for FieldValues {
+    fn from(keywords: Vec<String>) -> Self {
+        Self::Keywords(keywords)
+    }
+}
+
+impl From<Vec<&str>> for FieldValues {
+    fn from(keywords: Vec<&str>) -> Self {
+        Self::Keywords(keywords.into_iter().map(str::to_string).collect())
+    }
+}
+
+impl<const N: usize> From<[&str; N]> for FieldValues {
+    fn from(keywords: [&str; N]) -> Self {
+        Self::Keywords(keywords.into_iter().map(str::to_string).collect())
+    }
+}
+
+impl From<Vec<i64>> for FieldValues {
+    fn from(integers: Vec<i64>) -> Self {
+        Self::Integers(integers)
+    }
+}
+
+impl<const N: usize> From<[i64; N]> for FieldValues {
+    fn from(integers: [i64; N]) -> Self {
+        Self::Integers(integers.to_vec())
+    }
+}
+
+/// Bounds for [`Condition::Range`], any of which may be left open.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Range {
+    gt: Option<f64>,
+    gte: Option<f64>,
+    lt: Option<f64>,
+    lte: Option<f64>,
+}
+
+impl Range {
+    pub fn with_gt(mut self, gt: f64) -> Self {
+        self.gt = Some(gt);
+        self
+    }
+
+    pub fn with_gte(mut self, gte: f64) -> Self {
+        self.gte = Some(gte);
+        self
+    }
+
+    pub fn with_lt(mut self, lt: f64) -> Self {
+        self.lt = Some(lt);
+        self
+    }
+
+    pub fn with_lte(mut self, lte: f64) -> Self {
+        self.lte = Some(lte);
+        self
+    }
+
+    pub fn contains(&self, value: f64) -> bool {
+        self.gt.is_none_or(|gt| value > gt)
+            && self.gte.is_none_or(|gte| value >= gte)
+            && self.lt.is_none_or(|lt| value < lt)
+            && self.lte.is_none_or(|lte| value <= lte)
+    }
+}
+
+/// The non-null values at the dotted `key` of `payload`, with arrays along
+/// the way flattened.
+fn lookup<'a>(payload: &'a Value, key: &str) -> impl Iterator<Item = &'a Value> {
+    let mut values = vec![payload];
+    for part in key.split('.') {
+        values = values
+            .into_iter()
+            .filter_map(|value| value.get(part))
+            .flat_map(flatten)
+            .collect();
+    }
+    values.into_iter().filter(|value| !value.is_null())
+}
+
+fn flatten(value: &Value) -> Vec<&Value> {
+    match value {
+        Value::Array(items) => items.iter().collect(),
+        value => vec![value],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn payload() -> Value {
+        json!({
+            "path": "/photos/2023/beach.jpg",
+            "dirs": path_dirs("/photos/2023/beach.jpg"),
+            "extra": {
+                "camera": { "model": "X100", "iso": 400 },
+                "tags": ["sea", "sand"],
+                "rating": 4,
+                "album": null,
+            },
+        })
+    }
+
+    #[test]
+    fn test_field_conditions() {
+        let payload = payload();
+        let check = |condition: Condition| PayloadFilter::new().must(condition).matches(&payload);
+        assert!(check(Condition::matches(extra("camera.model"), "X100")));
+        assert!(!check(Condition::matches(extra("camera.model"), "X200")));
+        assert!(check(Condition::matches(extra("rating"), 4)));
+        assert!(check(Condition::any(extra("tags"), ["sand", "snow"])));
+        assert!(!check(Condition::any(extra("tags"), ["snow"])));
+        assert!(check(Condition::except(extra("rating"), [1, 2])));
+        assert!(!check(Condition::except(extra("missing"), [1])));
+        assert!(check(Condition::range(
+            extra("camera.iso"),
+            Range::default().with_gte(100.0).with_lt(800.0)
+        )));
+        assert!(!check(Condition::range(
+            extra("rating"),
+            Range::default().with_gt(4.0)
+        )));
+        assert!(check(Condition::is_empty(extra("album"))));
+        assert!(check(Condition::is_empty(extra("missing"))));
+        assert!(!check(Condition::is_empty(extra("tags"))));
+        assert!(check(Condition::path_prefix("/photos/2023/")));
+        assert!(check(Condition::path_prefix("/photos")));
+        assert!(check(Condition::path_prefix("/")));
+        assert!(!check(Condition::path_prefix("/photos/20")));
+        assert!(!check(Condition::path_prefix("/2023/")));
+    }
+
+    #[test]
+    fn test_combinations() {
+        let payload = payload();
+        let filter = PayloadFilter::new()
+            .must(Condition::path_prefix("/photos/"))
+            .must(
+                PayloadFilter::new()
+                    .should(Condition::matches(extra("rating"), 5))
+                    .should(Condition::any(extra("tags"), ["sea"])),
+            )
+            .must_not(Condition::matches(extra("camera.model"), "X200"));
+        assert!(filter.matches(&payload));
+        assert!(
+            !filter
+                .clone()
+                .must_not(Condition::matches(extra("tags"), "sand"))
+                .matches(&payload)
+        );
+        assert!(PayloadFilter::new().matches(&payload));
+        assert!(
+            !PayloadFilter::new()
+                .should(Condition::matches(extra("rating"), 1))
+                .matches(&payload)
+        );
+    }
+
+    #[test]
+    fn test_to_qdrant() {
+        let filter = PayloadFilter::new()
+            .must(Condition::matches(extra("camera.model"), "X 100"))
+            .should(Condition::is_empty("extra"))
+            .must_not(PayloadFilter::new().must(Condition::except("extra", [1])));
+        let compiled = filter.to_qdrant();
+        assert_eq!(compiled.must.len(), 1);
+        assert_eq!(compiled.should.len(), 1);
+        assert_eq!(compiled.must_not.len(), 1);
+        assert_eq!(
+            compiled.must[0],
+            qdrant::Condition::matches(
+                "extra.camera.model",
+                MatchValue::Keyword("X 100".to_string())
+            )
+        );
+
+        let compiled = PayloadFilter::new()
+            .must(Condition::path_prefix("/photos/"))
+            .to_qdrant();
+        assert_eq!(
+            compiled.must[0],
+            qdrant::Condition::matches("dirs", MatchValue::Keyword("/photos".to_string()))
+        );
+
+        let compiled = PayloadFilter::new()
+            .must(Condition::path_prefix("/photos/My Album/"))
+            .to_qdrant();
+        assert_eq!(
+            compiled.must[0],
+            qdrant::Condition::matches("dirs", MatchValue::Keyword("/photos/My Album".to_string()))
+        );
+    }
+}
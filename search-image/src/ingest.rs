@@ -0,0 +1,124 @@
+use crate::{
+    app::ImageInfo,
+    error::{Error, ItemError},
+};
+use serde::Deserialize;
+
+/// An input that was left out of an ingestion, with the reason.
+#[derive(Debug)]
+pub struct Skipped {
+    pub path: String,
+    pub error: ItemError,
+}
+
+/// What to do with an image that matches a point already in the collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePolicy {
+    /// Leave the existing point alone and do not store the image.
+    #[default]
+    Skip,
+    /// Store the image under the existing point's id, overwriting it.
+    Replace,
+    /// Store the image as a new point whose payload records the existing
+    /// point's id, see [`ImageInfo::duplicate_of`].
+    Link,
+}
+
+/// Similarity check run against the collection before each image is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct DuplicateCheck {
+    policy: DuplicatePolicy,
+    /// Score from which an image counts as a duplicate: a minimum similarity
+    /// for cosine and dot, a maximum distance for euclid and manhattan.
+    threshold: f32,
+}
+
+impl Default for DuplicateCheck {
+    fn default() -> Self {
+        Self {
+            policy: DuplicatePolicy::default(),
+            threshold: 0.95,
+        }
+    }
+}
+
+impl DuplicateCheck {
+    pub fn new(policy: DuplicatePolicy, threshold: f32) -> Self {
+        Self { policy, threshold }
+    }
+
+    pub fn policy(&self) -> DuplicatePolicy {
+        self.policy
+    }
+
+    pub fn threshold(&self) -> f32 {
+        self.threshold
+    }
+}
+
+/// An input that matched an existing point, and what was done about it.
+#[derive(Debug, Clone)]
+pub struct DuplicateMatch {
+    pub path: String,
+    /// Id of the point it matched, stored before or earlier in the same call.
+    pub existing: String,
+    pub score: f32,
+    pub action: DuplicatePolicy,
+}
+
+/// What an ingestion call stored and what it had to skip. Every input ends
+/// up in `added`, `skipped` or, when it was a duplicate that was not
+/// stored, only in `duplicates`.
+#[derive(Debug)]
+pub struct IngestReport<T = ()> {
+    pub added: Vec<ImageInfo<T>>,
+    pub skipped: Vec<Skipped>,
+    pub duplicates: Vec<DuplicateMatch>,
+}
+
+impl<T> Default for IngestReport<T> {
+    fn default() -> Self {
+        Self {
+            added: Vec::new(),
+            skipped: Vec::new(),
+            duplicates: Vec::new(),
+        }
+    }
+}
+
+impl<T> IngestReport<T> {
+    pub fn is_complete(&self) -> bool {
+        self.skipped.is_empty()
+    }
+
+    pub(crate) fn interrupted(self, error: Error) -> IngestError<T> {
+        IngestError {
+            report: self,
+            error,
+        }
+    }
+}
+
+/// An ingestion that stopped part way. `report` covers the chunks stored
+/// before `error`; the inputs from the failed chunk on are in none of its
+/// lists.
+#[derive(Debug, thiserror::Error)]
+#[error("{error}")]
+pub struct IngestError<T = ()> {
+    pub report: IngestReport<T>,
+    pub error: Error,
+}
+
+impl<T> From<Error> for IngestError<T> {
+    fn from(error: Error) -> Self {
+        IngestReport::default().interrupted(error)
+    }
+}
+
+impl<T> From<IngestError<T>> for Error {
+    fn from(error: IngestError<T>) -> Self {
+        error.error
+    }
+}
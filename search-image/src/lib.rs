@@ -1,8 +1,20 @@
 mod app;
 pub mod config;
 pub mod database;
+pub mod dedup;
 pub mod error;
 pub mod extractor;
+pub mod filter;
+pub mod ingest;
+pub mod migrate;
+pub mod model;
+pub mod preprocess;
+pub mod scan;
+pub mod schema;
+pub mod store;
+pub mod sync;
 pub mod utils;
+#[cfg(feature = "watch")]
+pub mod watch;
 
-pub use app::App;
+pub use app::{App, ImageInfo, SearchHit};
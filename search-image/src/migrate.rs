@@ -0,0 +1,420 @@
+use crate::{
+    App, ImageInfo,
+    error::{Error, Result},
+    ingest::Skipped,
+    schema::{self, CollectionMeta},
+    store::{QdrantStore, VectorStore},
+};
+use serde::{Deserialize, Serialize};
+use std::{future::Future, io::Write, path::Path};
+
+/// Source points re-embedded and written per step. The state is saved after
+/// each one, so this is also the most work an interruption can lose.
+const PAGE_SIZE: usize = 128;
+
+/// How far a migration got, see [`App::migrate_from`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigrationState {
+    /// Collections the migration reads from and writes to.
+    source: String,
+    target: String,
+    /// Source id the next page starts at, `None` before the first page.
+    offset: Option<String>,
+    migrated: usize,
+    skipped: usize,
+    done: bool,
+    /// Whether [`App::migrate_and_promote`] switched readers over.
+    #[serde(default)]
+    promoted: bool,
+}
+
+impl MigrationState {
+    /// Reads the state at `path`, or starts a new migration from `source` to
+    /// `target` if there is none. A state recorded for other collections is
+    /// refused rather than resumed.
+    pub fn load(path: impl AsRef<Path>, source: &str, target: &str) -> Result<Self> {
+        let path = path.as_ref();
+        let state: Self = match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| Error::MigrationError(format!("{}: {e}", path.display())))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self {
+                    source: source.to_string(),
+                    target: target.to_string(),
+                    ..Self::default()
+                });
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if state.source != source || state.target != target {
+            return Err(Error::MigrationError(format!(
+                "{}: records a migration from `{}` to `{}`, not from `{source}` to `{target}`",
+                path.display(),
+                state.source,
+                state.target
+            )));
+        }
+        Ok(state)
+    }
+
+    /// Writes the state through a temporary file, flushed to disk before the
+    /// rename, so a crash leaves either the old state or the new one.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(&serde_json::to_vec_pretty(self)?)?;
+        file.sync_all()?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Points written to the new collection so far.
+    pub fn migrated(&self) -> usize {
+        self.migrated
+    }
+
+    /// Points left behind because their image could not be read again.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    pub fn is_promoted(&self) -> bool {
+        self.promoted
+    }
+}
+
+/// Switches readers over to the collection a finished migration filled, see
+/// [`App::migrate_and_promote`]. Running it again after it succeeded must
+/// leave readers where they are.
+pub trait Promotion: Send + Sync {
+    fn promote(&self) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// Points a Qdrant alias at the target store's collection. Qdrant moves an
+/// existing alias in a single operation, so clients reading through it
+/// never find it missing.
+pub struct PointAlias<'a> {
+    target: &'a QdrantStore,
+    alias: &'a str,
+}
+
+impl<'a> PointAlias<'a> {
+    pub fn new(target: &'a QdrantStore, alias: &'a str) -> Self {
+        Self { target, alias }
+    }
+}
+
+impl Promotion for PointAlias<'_> {
+    async fn promote(&self) -> Result<()> {
+        self.target.point_alias(self.alias).await
+    }
+}
+
+/// Outcome of one [`App::migrate_from`] call.
+#[derive(Debug)]
+pub struct MigrationReport {
+    pub state: MigrationState,
+    /// Points skipped during this call, with the reason.
+    pub skipped: Vec<Skipped>,
+}
+
+impl<S: VectorStore> App<S> {
+    /// Re-embeds every point of `source` with this app's extractor and stores
+    /// it in this app's collection, keeping its id and payload. The `dirs` of
+    /// the payload are recomputed, so that points stored before they were
+    /// recorded match [`Condition::PathPrefix`] afterwards.
+    ///
+    /// The collection gets the [`CollectionMeta`] record of this app's
+    /// extractor and store metric. Images are read again from the `path` of
+    /// their payload. The position is saved to `state_path` after every page
+    /// and handed to `on_progress`, and calling this again with the same file
+    /// resumes where a stopped run left off. `names` are the source and target
+    /// collection names, recorded in the state so that it is never resumed
+    /// against other collections. `source` is only read, so it can keep
+    /// serving searches until [`App::migrate_and_promote`],
+    /// [`QdrantStore::promote`] or [`QdrantStore::point_alias`] switches
+    /// readers over.
+    /// Points added to `source` during the run are only picked up if their
+    /// id sorts after the current position.
+    ///
+    /// [`Condition::PathPrefix`]: crate::filter::Condition::PathPrefix
+    /// [`QdrantStore::promote`]: crate::store::QdrantStore::promote
+    /// [`QdrantStore::point_alias`]: crate::store::QdrantStore::point_alias
+    pub async fn migrate_from<F: VectorStore>(
+        &self,
+        source: &F,
+        names: (&str, &str),
+        state_path: impl AsRef<Path>,
+        mut on_progress: impl FnMut(&MigrationState),
+    ) -> Result<MigrationReport> {
+        let (source_name, target_name) = names;
+        let expected = CollectionMeta {
+            metric: self.store().metric(),
+            ..CollectionMeta::for_extractor(self.extractor())
+        };
+        schema::verify(self.store(), target_name, &expected).await?;
+        let state_path = state_path.as_ref();
+        let mut state = MigrationState::load(state_path, source_name, target_name)?;
+        let mut skipped = Vec::new();
+        let version = self.extractor().preprocessor().version();
+        while !state.done {
+            let page = source
+                .scroll::<serde_json::Value>(state.offset.as_deref(), PAGE_SIZE)
+                .await?;
+            let paths = page
+                .items
+                .iter()
+                .map(|info| info.path().to_string())
+                .collect::<Vec<_>>();
+
+            let mut items = page.items.into_iter();
+            let (mut features, mut infos) = (Vec::new(), Vec::<ImageInfo<_>>::new());
+            for chunk in self.extractor().extract_hashed_chunks(&paths) {
+                for ((path, result), info) in chunk?.into_iter().zip(items.by_ref()) {
+                    match result {
+                        Ok((feature, hash)) => {
+                            features.push(feature);
+                            infos.push(info.with_dirs().with_preprocess(&version).with_hash(hash));
+                        }
+                        Err(error) => skipped.push(Skipped {
+                            path: path.clone(),
+                            error,
+                        }),
+                    }
+                }
+            }
+            if !infos.is_empty() {
+                self.store().add(&features, &infos).await?;
+            }
+
+            state.migrated += infos.len();
+            state.skipped += paths.len() - infos.len();
+            state.done = page.next_offset.is_none();
+            state.offset = page.next_offset;
+            state.save(state_path)?;
+            on_progress(&state);
+        }
+        Ok(MigrationReport { state, skipped })
+    }
+
+    /// Runs [`App::migrate_from`] to the end, then `promotion`, typically a
+    /// [`PointAlias`] swapping the alias readers use over to the new
+    /// collection. The promotion is recorded in the state, so resuming a
+    /// promoted migration does nothing, and one that failed to promote only
+    /// retries the promotion.
+    pub async fn migrate_and_promote<F: VectorStore>(
+        &self,
+        source: &F,
+        names: (&str, &str),
+        state_path: impl AsRef<Path>,
+        promotion: &impl Promotion,
+        on_progress: impl FnMut(&MigrationState),
+    ) -> Result<MigrationReport> {
+        let state_path = state_path.as_ref();
+        let mut report = self
+            .migrate_from(source, names, state_path, on_progress)
+            .await?;
+        if report.state.done && !report.state.promoted {
+            promotion.promote().await?;
+            report.state.promoted = true;
+            report.state.save(state_path)?;
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        extractor::Extractor,
+        filter::{Condition, PayloadFilter},
+        model::tests::MeanColor,
+        store::{MemoryStore, SearchOptions},
+        utils::tests::TempDir,
+    };
+    use candle_core::Device;
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    const NAMES: (&str, &str) = ("images_v1", "images_v2");
+
+    async fn setup(dir: &Path) -> (MemoryStore, App<MemoryStore>) {
+        let mut paths = Vec::new();
+        for (name, color) in [("a", [255, 0, 0]), ("b", [0, 255, 0])] {
+            let path = dir.join(format!("{name}.png"));
+            image::RgbImage::from_pixel(16, 16, image::Rgb(color))
+                .save(&path)
+                .unwrap();
+            paths.push(path);
+        }
+        paths.push(dir.join("c.png"));
+
+        // Payloads as stored before `dirs` and `preprocess` were recorded.
+        let source = MemoryStore::new(3);
+        let infos = ["a", "b", "c"]
+            .iter()
+            .zip(&paths)
+            .map(|(id, path)| {
+                let payload = serde_json::json!({ "path": path, "extra": id });
+                ImageInfo::<String>::from_payload(id.to_string(), payload).unwrap()
+            })
+            .collect::<Vec<_>>();
+        source
+            .add(&vec![vec![1.0, 0.0, 0.0]; 3], &infos)
+            .await
+            .unwrap();
+
+        let extractor = Extractor::from_model(Arc::new(MeanColor), &Device::Cpu);
+        (source, App::with_store(MemoryStore::new(2), extractor))
+    }
+
+    #[tokio::test]
+    async fn test_migrate_keeps_ids_and_payloads() {
+        let dir = TempDir::new("migrate");
+        let (source, app) = setup(&dir).await;
+        let state_path = dir.join("migration.json");
+
+        let mut pages = 0;
+        let report = app
+            .migrate_from(&source, NAMES, &state_path, |_| pages += 1)
+            .await
+            .unwrap();
+        assert_eq!(pages, 1);
+        assert!(report.state.is_done());
+        assert_eq!(report.state.migrated(), 2);
+        assert_eq!(report.state.skipped(), 1);
+        assert!(report.skipped[0].path.ends_with("c.png"));
+
+        let hits = app.search_feature::<String>(&[0.0, 1.0], 3).await.unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].id(), "b");
+        assert_eq!(hits[0].extra().map(String::as_str), Some("b"));
+        assert!(hits[0].preprocess().is_some());
+
+        let prefix = PayloadFilter::new().must(Condition::path_prefix(dir.to_str().unwrap()));
+        let hits = app
+            .search_feature_with::<String>(&[0.0, 1.0], SearchOptions::new(3).with_filter(prefix))
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 2);
+
+        // A finished migration is not run again.
+        let report = app
+            .migrate_from(&source, NAMES, &state_path, |_| unreachable!())
+            .await
+            .unwrap();
+        assert_eq!(report.state.migrated(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_migrate_resumes_from_state() {
+        let dir = TempDir::new("migrate");
+        let (source, app) = setup(&dir).await;
+        let state_path = dir.join("migration.json");
+        MigrationState {
+            offset: Some("b".to_string()),
+            migrated: 1,
+            ..MigrationState::load(&state_path, NAMES.0, NAMES.1).unwrap()
+        }
+        .save(&state_path)
+        .unwrap();
+
+        let report = app
+            .migrate_from(&source, NAMES, &state_path, |_| {})
+            .await
+            .unwrap();
+        assert_eq!(report.state.migrated(), 2);
+        assert!(app.get::<String>(&["a"]).await.unwrap().is_empty());
+        assert_eq!(app.get::<String>(&["b"]).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_state_of_other_collections_is_refused() {
+        let dir = TempDir::new("migrate");
+        let (source, app) = setup(&dir).await;
+        let state_path = dir.join("migration.json");
+        MigrationState::load(&state_path, "images_v1", "images_v3")
+            .unwrap()
+            .save(&state_path)
+            .unwrap();
+
+        let err = app
+            .migrate_from(&source, NAMES, &state_path, |_| unreachable!())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::MigrationError(_)));
+        assert!(app.get::<String>(&["a"]).await.unwrap().is_empty());
+    }
+
+    /// Counts its calls, failing the first `failures` of them.
+    #[derive(Default)]
+    struct CountingPromotion {
+        calls: AtomicUsize,
+        failures: usize,
+    }
+
+    impl Promotion for CountingPromotion {
+        async fn promote(&self) -> Result<()> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(Error::CollectionError("alias update failed".to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_promotes_once_after_migrating() {
+        let dir = TempDir::new("migrate");
+        let (source, app) = setup(&dir).await;
+        let state_path = dir.join("migration.json");
+        let promotion = CountingPromotion {
+            failures: 1,
+            ..Default::default()
+        };
+
+        // The data is migrated even when the promotion fails.
+        let err = app
+            .migrate_and_promote(&source, NAMES, &state_path, &promotion, |_| {})
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::CollectionError(_)));
+        let state = MigrationState::load(&state_path, NAMES.0, NAMES.1).unwrap();
+        assert!(state.is_done());
+        assert!(!state.is_promoted());
+
+        // Resuming only retries the promotion.
+        let report = app
+            .migrate_and_promote(&source, NAMES, &state_path, &promotion, |_| unreachable!())
+            .await
+            .unwrap();
+        assert!(report.state.is_promoted());
+        assert_eq!(promotion.calls.load(Ordering::SeqCst), 2);
+        assert!(
+            MigrationState::load(&state_path, NAMES.0, NAMES.1)
+                .unwrap()
+                .is_promoted()
+        );
+
+        // A promoted migration is left alone.
+        app.migrate_and_promote(&source, NAMES, &state_path, &promotion, |_| unreachable!())
+            .await
+            .unwrap();
+        assert_eq!(promotion.calls.load(Ordering::SeqCst), 2);
+    }
+}
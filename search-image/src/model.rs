@@ -0,0 +1,252 @@
+use crate::{
+    config::NetworkKind,
+    error::{Error, Result},
+    preprocess::Preprocessor,
+};
+use candle_core::{DType, Device, Tensor};
+use candle_nn::{Func, Module, VarBuilder};
+use candle_transformers::models::{
+    clip::{ClipConfig, ClipModel},
+    convnext, mobilenetv4,
+};
+use tokenizers::Tokenizer;
+
+/// A backbone turning preprocessed images into feature vectors.
+///
+/// The [`Extractor`](crate::extractor::Extractor) and the collection it feeds
+/// are sized and configured from these methods, so nothing outside the model
+/// assumes a particular architecture.
+pub trait FeatureModel: std::fmt::Debug + Send + Sync {
+    /// Identifies the architecture and its weights. It is recorded with the
+    /// collection, so that vectors of different models never get mixed.
+    fn name(&self) -> String;
+
+    /// Length of the feature of one image.
+    fn dim(&self) -> usize;
+
+    /// Side of the square images the model expects.
+    fn resolution(&self) -> u32;
+
+    /// Per-channel `(mean, std)` the model was trained with.
+    fn normalization(&self) -> ([f32; 3], [f32; 3]);
+
+    /// The preprocessing matching the model's training.
+    fn preprocessor(&self) -> Preprocessor {
+        let (mean, std) = self.normalization();
+        Preprocessor::new(self.resolution(), mean, std)
+    }
+
+    /// Maps a `(batch, 3, resolution, resolution)` tensor to `(batch, dim)`.
+    fn forward(&self, images: &Tensor) -> Result<Tensor>;
+
+    /// Embeds `text` into the image feature space, for joint image-text
+    /// models.
+    fn encode_text(&self, _text: &str) -> Result<Tensor> {
+        Err(Error::TextNotSupported(self.name()))
+    }
+}
+
+/// MobileNetV4 without its classification head.
+#[derive(Debug)]
+pub struct MobileNetV4 {
+    name: String,
+    dim: usize,
+    resolution: u32,
+    normalization: ([f32; 3], [f32; 3]),
+    network: Func<'static>,
+}
+
+impl MobileNetV4 {
+    pub fn load(kind: NetworkKind, vb: VarBuilder) -> Result<Self> {
+        let config = kind
+            .mobilenet_config()
+            .ok_or_else(|| Error::ModelError(format!("{kind:?} is not a MobileNetV4 network")))?;
+        let network = mobilenetv4::mobilenetv4_no_final_layer(&config, vb.clone())?;
+        Ok(Self {
+            name: kind.model_filename(),
+            dim: output_dim(&network, kind.resolution(), vb.device())?,
+            resolution: kind.resolution(),
+            normalization: kind.normalization(),
+            network,
+        })
+    }
+}
+
+impl FeatureModel for MobileNetV4 {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn resolution(&self) -> u32 {
+        self.resolution
+    }
+
+    fn normalization(&self) -> ([f32; 3], [f32; 3]) {
+        self.normalization
+    }
+
+    fn forward(&self, images: &Tensor) -> Result<Tensor> {
+        Ok(self.network.forward(images)?.flatten_from(1)?)
+    }
+}
+
+/// ConvNeXt without its classification head, globally average pooled.
+#[derive(Debug)]
+pub struct ConvNext {
+    name: String,
+    dim: usize,
+    resolution: u32,
+    normalization: ([f32; 3], [f32; 3]),
+    network: Func<'static>,
+}
+
+impl ConvNext {
+    pub fn load(kind: NetworkKind, vb: VarBuilder) -> Result<Self> {
+        let config = match kind {
+            NetworkKind::ConvnextTiny => convnext::Config::tiny(),
+            NetworkKind::ConvnextBase => convnext::Config::base(),
+            _ => {
+                return Err(Error::ModelError(format!(
+                    "{kind:?} is not a ConvNeXt network"
+                )));
+            }
+        };
+        let network = convnext::convnext_no_final_layer(&config, vb.clone())?;
+        Ok(Self {
+            name: kind.model_filename(),
+            dim: output_dim(&network, kind.resolution(), vb.device())?,
+            resolution: kind.resolution(),
+            normalization: kind.normalization(),
+            network,
+        })
+    }
+}
+
+impl FeatureModel for ConvNext {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn resolution(&self) -> u32 {
+        self.resolution
+    }
+
+    fn normalization(&self) -> ([f32; 3], [f32; 3]) {
+        self.normalization
+    }
+
+    fn forward(&self, images: &Tensor) -> Result<Tensor> {
+        Ok(self.network.forward(images)?)
+    }
+}
+
+/// Width of the features `network` produces. The configurations do not
+/// expose it once the head is cut off, so one blank image is run through.
+fn output_dim(network: &Func<'static>, resolution: u32, device: &Device) -> Result<usize> {
+    let side = resolution as usize;
+    let probe = Tensor::zeros((1, 3, side, side), DType::F32, device)?;
+    Ok(network.forward(&probe)?.flatten_from(1)?.dim(1)?)
+}
+
+/// CLIP ViT-B/32, with the text encoder sharing its embedding space.
+#[derive(Debug)]
+pub struct Clip {
+    model: ClipModel,
+    dim: usize,
+    resolution: u32,
+    tokenizer: Tokenizer,
+    max_len: usize,
+    device: Device,
+}
+
+impl Clip {
+    pub fn load(vb: VarBuilder, tokenizer: Tokenizer) -> Result<Self> {
+        let config = ClipConfig::vit_base_patch32();
+        let device = vb.device().clone();
+        Ok(Self {
+            model: ClipModel::new(vb, &config)?,
+            dim: config.vision_config.projection_dim,
+            resolution: config.vision_config.image_size as u32,
+            tokenizer,
+            max_len: config.text_config.max_position_embeddings,
+            device,
+        })
+    }
+}
+
+impl FeatureModel for Clip {
+    fn name(&self) -> String {
+        NetworkKind::Clip.model_filename()
+    }
+
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn resolution(&self) -> u32 {
+        self.resolution
+    }
+
+    fn normalization(&self) -> ([f32; 3], [f32; 3]) {
+        NetworkKind::Clip.normalization()
+    }
+
+    fn forward(&self, images: &Tensor) -> Result<Tensor> {
+        Ok(self.model.get_image_features(images)?)
+    }
+
+    fn encode_text(&self, text: &str) -> Result<Tensor> {
+        let encoding = self
+            .tokenizer
+            .encode(text, true)
+            .map_err(|e| Error::TokenizerError(e.to_string()))?;
+        let mut ids = encoding.get_ids().to_vec();
+        // The pooled output is read at the end-of-text token, keep it last.
+        if ids.len() > self.max_len {
+            let end = ids[ids.len() - 1];
+            ids.truncate(self.max_len - 1);
+            ids.push(end);
+        }
+        let input_ids = Tensor::new(ids.as_slice(), &self.device)?.unsqueeze(0)?;
+        Ok(self.model.get_text_features(&input_ids)?)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Maps every image to its mean red and green, enough to tell them apart.
+    #[derive(Debug)]
+    pub(crate) struct MeanColor;
+
+    impl FeatureModel for MeanColor {
+        fn name(&self) -> String {
+            "mean-color".to_string()
+        }
+
+        fn dim(&self) -> usize {
+            2
+        }
+
+        fn resolution(&self) -> u32 {
+            8
+        }
+
+        fn normalization(&self) -> ([f32; 3], [f32; 3]) {
+            ([0.0; 3], [1.0; 3])
+        }
+
+        fn forward(&self, images: &Tensor) -> Result<Tensor> {
+            Ok(images.to_dtype(DType::F32)?.mean((2, 3))?.narrow(1, 0, 2)?)
+        }
+    }
+}
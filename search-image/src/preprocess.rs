@@ -0,0 +1,214 @@
+use crate::{config::NetworkKind, error::Result};
+use candle_core::{DType, Device, Tensor};
+use image::{DynamicImage, Rgb, RgbImage, imageops::FilterType};
+use serde::Deserialize;
+
+/// Bumped whenever the tensor produced for the same settings changes, so that
+/// embeddings written by an older pipeline are never compared with new ones.
+///
+/// - 1: `(width, height)` tensor layout, `[0, 1]` scaling only.
+/// - 2: `(height, width)` layout and per-channel mean/std normalization.
+pub const PIPELINE_VERSION: u32 = 2;
+
+/// How an image is brought to the square model resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResizeMode {
+    /// Stretch to the target size, ignoring the aspect ratio.
+    Fill,
+    /// Scale the shorter side to the target size and crop the centre.
+    #[default]
+    CenterCrop,
+    /// Scale the longer side to the target size and pad the rest with the
+    /// mean colour, which becomes zero after normalization.
+    Letterbox,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    Nearest,
+    Triangle,
+    /// Bicubic, the interpolation the timm checkpoints were trained with.
+    #[default]
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl From<Filter> for FilterType {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Nearest => FilterType::Nearest,
+            Filter::Triangle => FilterType::Triangle,
+            Filter::CatmullRom => FilterType::CatmullRom,
+            Filter::Gaussian => FilterType::Gaussian,
+            Filter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// User-facing part of the pipeline, read from the `[mobilenet.preprocess]`
+/// table. Resolution and normalization always come from the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(default)]
+pub struct PreprocessConfig {
+    pub resize: ResizeMode,
+    pub filter: Filter,
+}
+
+/// Turns decoded images into normalized `(3, height, width)` tensors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Preprocessor {
+    resolution: u32,
+    resize: ResizeMode,
+    filter: Filter,
+    mean: [f32; 3],
+    std: [f32; 3],
+}
+
+impl Preprocessor {
+    /// Default resize and filter for square `resolution` inputs normalized
+    /// with `mean` and `std`.
+    pub fn new(resolution: u32, mean: [f32; 3], std: [f32; 3]) -> Self {
+        Self {
+            resolution,
+            resize: ResizeMode::default(),
+            filter: Filter::default(),
+            mean,
+            std,
+        }
+    }
+
+    /// The pipeline `kind` was trained with.
+    pub fn for_network(kind: NetworkKind) -> Self {
+        let (mean, std) = kind.normalization();
+        Self::new(kind.resolution(), mean, std)
+    }
+
+    pub fn with_config(self, config: PreprocessConfig) -> Self {
+        self.with_resize(config.resize).with_filter(config.filter)
+    }
+
+    pub fn with_resize(mut self, resize: ResizeMode) -> Self {
+        self.resize = resize;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_normalization(mut self, mean: [f32; 3], std: [f32; 3]) -> Self {
+        self.mean = mean;
+        self.std = std;
+        self
+    }
+
+    pub fn resolution(&self) -> u32 {
+        self.resolution
+    }
+
+    pub fn resize(&self) -> ResizeMode {
+        self.resize
+    }
+
+    pub fn filter(&self) -> Filter {
+        self.filter
+    }
+
+    pub fn mean(&self) -> [f32; 3] {
+        self.mean
+    }
+
+    pub fn std(&self) -> [f32; 3] {
+        self.std
+    }
+
+    /// Identifies every setting that influences the produced tensor. Stored
+    /// with each point so searches only compare like with like.
+    pub fn version(&self) -> String {
+        format!(
+            "v{PIPELINE_VERSION}/{:?}/{:?}/{}/{:?}/{:?}",
+            self.resize, self.filter, self.resolution, self.mean, self.std
+        )
+    }
+
+    pub fn resize_image(&self, image: &DynamicImage) -> RgbImage {
+        let size = self.resolution;
+        let filter = self.filter.into();
+        match self.resize {
+            ResizeMode::Fill => image.resize_exact(size, size, filter).into_rgb8(),
+            ResizeMode::CenterCrop => image.resize_to_fill(size, size, filter).into_rgb8(),
+            ResizeMode::Letterbox => {
+                let fitted = image.resize(size, size, filter).into_rgb8();
+                let fill = self.mean.map(|m| (m * 255.0).round() as u8);
+                let mut canvas = RgbImage::from_pixel(size, size, Rgb(fill));
+                let x = (size - fitted.width()) / 2;
+                let y = (size - fitted.height()) / 2;
+                image::imageops::replace(&mut canvas, &fitted, x as i64, y as i64);
+                canvas
+            }
+        }
+    }
+
+    pub fn apply(&self, image: &DynamicImage) -> Result<Tensor> {
+        let img = self.resize_image(image);
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let data = Tensor::from_vec(img.into_raw(), (height, width, 3), &Device::Cpu)?
+            .permute((2, 0, 1))?
+            .to_dtype(DType::F32)?;
+        let mean = Tensor::new(&self.mean, &Device::Cpu)?.reshape((3, 1, 1))?;
+        let std = Tensor::new(&self.std, &Device::Cpu)?.reshape((3, 1, 1))?;
+        Ok((data / 255.0)?.broadcast_sub(&mean)?.broadcast_div(&std)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_non_square_layout() {
+        let mut img = RgbImage::new(4, 2);
+        img.put_pixel(3, 0, Rgb([255, 0, 0]));
+        let preprocessor = Preprocessor {
+            resolution: 4,
+            resize: ResizeMode::Fill,
+            filter: Filter::Nearest,
+            mean: [0.0; 3],
+            std: [1.0; 3],
+        };
+        let tensor = preprocessor.apply(&DynamicImage::ImageRgb8(img)).unwrap();
+        assert_eq!(tensor.dims(), [3, 4, 4]);
+        let red = tensor.get(0).unwrap().to_vec2::<f32>().unwrap();
+        assert_eq!(red[0][3], 1.0);
+        assert_eq!(red[3][0], 0.0);
+    }
+
+    #[test]
+    fn test_letterbox_pads_with_mean() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(8, 4, Rgb([255, 255, 255])));
+        let preprocessor =
+            Preprocessor::for_network(NetworkKind::Small).with_resize(ResizeMode::Letterbox);
+        let resized = preprocessor.resize_image(&img);
+        assert_eq!(resized.dimensions(), (224, 224));
+        let tensor = preprocessor.apply(&img).unwrap();
+        let corner = tensor.get(0).unwrap().get(0).unwrap().get(0).unwrap();
+        assert!(corner.to_scalar::<f32>().unwrap().abs() < 0.02);
+    }
+
+    #[test]
+    fn test_version_tracks_settings() {
+        let base = Preprocessor::for_network(NetworkKind::Small);
+        assert_ne!(
+            base.version(),
+            base.with_resize(ResizeMode::Letterbox).version()
+        );
+        assert_ne!(
+            base.version(),
+            Preprocessor::for_network(NetworkKind::Large).version()
+        );
+    }
+}
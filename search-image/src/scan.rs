@@ -0,0 +1,371 @@
+use crate::error::{Error, Result};
+use glob::{MatchOptions, Pattern};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Extensions accepted by [`FolderScan::default`], matching the formats the
+/// `image` crate decodes out of the box.
+pub const IMAGE_EXTENSIONS: &[&str] = &[
+    "avif", "bmp", "gif", "ico", "jpeg", "jpg", "png", "pnm", "tga", "tif", "tiff", "webp",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkPolicy {
+    /// Ignore symbolic links to files and directories.
+    #[default]
+    Skip,
+    /// Treat links like their targets. Links that loop back to one of their
+    /// ancestors are ignored.
+    Follow,
+}
+
+/// Rules for collecting image files under a folder.
+///
+/// Glob patterns are matched against the path relative to the scanned root,
+/// with `/` as separator, so `raw/**` excludes everything below `raw` and
+/// `**/*.png` only keeps PNG files. A directory matching an exclude pattern is
+/// not descended into.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FolderScan {
+    recursive: bool,
+    max_depth: Option<usize>,
+    extensions: Vec<String>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    hidden: bool,
+    symlinks: SymlinkPolicy,
+}
+
+impl Default for FolderScan {
+    fn default() -> Self {
+        Self {
+            recursive: true,
+            max_depth: None,
+            extensions: IMAGE_EXTENSIONS.iter().map(|ext| ext.to_string()).collect(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            hidden: false,
+            symlinks: SymlinkPolicy::default(),
+        }
+    }
+}
+
+impl FolderScan {
+    /// Only the files directly inside the folder.
+    pub fn flat() -> Self {
+        Self::default().recursive(false)
+    }
+
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Deepest level to descend to, `1` being the files in the root itself.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Replaces the extension allowlist. An empty list accepts any file.
+    pub fn extensions<S: AsRef<str>>(mut self, extensions: &[S]) -> Self {
+        self.extensions = extensions
+            .iter()
+            .map(|ext| ext.as_ref().to_string())
+            .collect();
+        self
+    }
+
+    pub fn include(mut self, pattern: &str) -> Self {
+        self.include.push(pattern.to_string());
+        self
+    }
+
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.exclude.push(pattern.to_string());
+        self
+    }
+
+    /// Whether files and directories starting with `.` are visited.
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    pub fn symlinks(mut self, symlinks: SymlinkPolicy) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Lists the files under `root` that pass every rule, sorted by path.
+    pub fn scan(&self, root: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let root = root.as_ref();
+        if !root.is_dir() {
+            return Err(Error::FolderNotFound(root.to_string_lossy().to_string()));
+        }
+        let include = compile(&self.include)?;
+        let exclude = compile(&self.exclude)?;
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        let relative = |path: &Path| {
+            path.strip_prefix(root)
+                .unwrap_or(path)
+                .to_string_lossy()
+                .replace(std::path::MAIN_SEPARATOR, "/")
+        };
+
+        let max_depth = match (self.recursive, self.max_depth) {
+            (false, _) => 1,
+            (true, Some(depth)) => depth,
+            (true, None) => usize::MAX,
+        };
+        let walker = walkdir::WalkDir::new(root)
+            .min_depth(1)
+            .max_depth(max_depth)
+            .follow_links(self.symlinks == SymlinkPolicy::Follow)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| {
+                let hidden = entry.file_name().to_string_lossy().starts_with('.');
+                let rel = relative(entry.path());
+                (self.hidden || !hidden)
+                    && !exclude
+                        .iter()
+                        .any(|pattern| pattern.matches_with(&rel, options))
+            });
+
+        let mut paths = Vec::new();
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) if e.loop_ancestor().is_some() => continue,
+                Err(e) => return Err(Error::IOError(e.into())),
+            };
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            if !self.extensions.is_empty() {
+                let ext = entry.path().extension().map(|ext| ext.to_string_lossy());
+                if !ext
+                    .is_some_and(|ext| self.extensions.iter().any(|e| e.eq_ignore_ascii_case(&ext)))
+                {
+                    continue;
+                }
+            }
+            let rel = relative(entry.path());
+            if !include.is_empty()
+                && !include
+                    .iter()
+                    .any(|pattern| pattern.matches_with(&rel, options))
+            {
+                continue;
+            }
+            paths.push(entry.into_path());
+        }
+        Ok(paths)
+    }
+
+    /// Whether [`scan`](Self::scan) of `root` would list the file at `path`,
+    /// judged from the path alone. Used to filter filesystem events without
+    /// walking the whole tree.
+    pub fn accepts(&self, root: impl AsRef<Path>, path: impl AsRef<Path>) -> Result<bool> {
+        Ok(self.filter(root.as_ref())?.accepts(path))
+    }
+
+    /// [`accepts`](Self::accepts) for many paths under `root`, with the
+    /// glob patterns compiled once.
+    pub fn filter<'a>(&'a self, root: &'a Path) -> Result<PathFilter<'a>> {
+        Ok(PathFilter {
+            scan: self,
+            root,
+            include: compile(&self.include)?,
+            exclude: compile(&self.exclude)?,
+        })
+    }
+}
+
+/// The rules of a [`FolderScan`] applied to single paths, see
+/// [`FolderScan::filter`].
+#[derive(Debug)]
+pub struct PathFilter<'a> {
+    scan: &'a FolderScan,
+    root: &'a Path,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl PathFilter<'_> {
+    pub fn accepts(&self, path: impl AsRef<Path>) -> bool {
+        let scan = self.scan;
+        let path = path.as_ref();
+        let Ok(rel) = path.strip_prefix(self.root) else {
+            return false;
+        };
+        let components = rel
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        let depth = components.len();
+        if depth == 0
+            || (!scan.recursive && depth > 1)
+            || scan.max_depth.is_some_and(|max| depth > max)
+            || (!scan.hidden && components.iter().any(|c| c.starts_with('.')))
+        {
+            return false;
+        }
+        if scan.symlinks == SymlinkPolicy::Skip
+            && path
+                .symlink_metadata()
+                .is_ok_and(|metadata| metadata.file_type().is_symlink())
+        {
+            return false;
+        }
+        if !scan.extensions.is_empty() {
+            let ext = path.extension().map(|ext| ext.to_string_lossy());
+            if !ext.is_some_and(|ext| scan.extensions.iter().any(|e| e.eq_ignore_ascii_case(&ext)))
+            {
+                return false;
+            }
+        }
+
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        // Excluding a directory excludes everything below it.
+        let excluded = (1..=depth).any(|n| {
+            let prefix = components[..n].join("/");
+            self.exclude
+                .iter()
+                .any(|pattern| pattern.matches_with(&prefix, options))
+        });
+        let rel = components.join("/");
+        !excluded
+            && (self.include.is_empty()
+                || self
+                    .include
+                    .iter()
+                    .any(|pattern| pattern.matches_with(&rel, options)))
+    }
+}
+
+fn compile(patterns: &[String]) -> Result<Vec<Pattern>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Pattern::new(pattern).map_err(|e| Error::InvalidPattern(format!("{pattern}: {e}")))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TempDir;
+
+    fn tree() -> TempDir {
+        let root = TempDir::new("scan");
+        for file in [
+            "a.png",
+            "b.JPG",
+            "notes.txt",
+            ".DS_Store",
+            ".hidden/c.png",
+            "trips/d.png",
+            "trips/raw/e.png",
+            "trips/2024/f.webp",
+        ] {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"").unwrap();
+        }
+        root
+    }
+
+    fn names(root: &Path, paths: Vec<PathBuf>) -> Vec<String> {
+        paths
+            .iter()
+            .map(|path| {
+                path.strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_default_is_recursive_images_only() {
+        let root = tree();
+        let found = FolderScan::default().scan(&root).unwrap();
+        assert_eq!(
+            names(&root, found),
+            [
+                "a.png",
+                "b.JPG",
+                "trips/2024/f.webp",
+                "trips/d.png",
+                "trips/raw/e.png"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_depth_hidden_and_patterns() {
+        let root = tree();
+        let flat = FolderScan::flat().scan(&root).unwrap();
+        assert_eq!(names(&root, flat), ["a.png", "b.JPG"]);
+
+        let hidden = FolderScan::default()
+            .hidden(true)
+            .max_depth(2)
+            .scan(&root)
+            .unwrap();
+        assert_eq!(
+            names(&root, hidden),
+            [".hidden/c.png", "a.png", "b.JPG", "trips/d.png"]
+        );
+
+        let filtered = FolderScan::default()
+            .exclude("trips/raw")
+            .include("**/*.png")
+            .scan(&root)
+            .unwrap();
+        assert_eq!(names(&root, filtered), ["a.png", "trips/d.png"]);
+    }
+
+    #[test]
+    fn test_accepts_agrees_with_scan() {
+        let root = tree();
+        let scan = FolderScan::default().exclude("trips/raw").max_depth(2);
+        let found = scan.scan(&root).unwrap();
+        for file in [
+            "a.png",
+            "b.JPG",
+            "notes.txt",
+            ".hidden/c.png",
+            "trips/d.png",
+            "trips/raw/e.png",
+            "trips/2024/f.webp",
+        ] {
+            let path = root.join(file);
+            assert_eq!(
+                scan.accepts(&root, &path).unwrap(),
+                found.contains(&path),
+                "{file}"
+            );
+        }
+        assert!(!scan.accepts(&root, "/elsewhere/a.png").unwrap());
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let root = tree();
+        let err = FolderScan::default().include("[").scan(&root).unwrap_err();
+        assert!(matches!(err, Error::InvalidPattern(_)));
+    }
+}
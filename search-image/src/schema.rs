@@ -0,0 +1,217 @@
+use crate::{
+    config::{Metric, NetworkKind},
+    error::{Error, Result},
+    extractor::Extractor,
+    store::VectorStore,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// What produced the vectors of a collection. It is stored next to the
+/// collection when it is created, and checked against the running
+/// [`Extractor`] on startup, so vectors from different models or
+/// preprocessing pipelines never end up side by side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "StoredMeta")]
+pub struct CollectionMeta {
+    /// [`FeatureModel::name`](crate::model::FeatureModel::name) of the model.
+    pub model: String,
+    pub dim: usize,
+    /// [`Preprocessor::version`](crate::preprocess::Preprocessor::version) of
+    /// the pipeline feeding the model.
+    pub preprocess: String,
+    pub metric: Metric,
+}
+
+impl CollectionMeta {
+    /// The record matching the vectors `extractor` produces, compared with
+    /// the default [`Metric`].
+    pub fn for_extractor(extractor: &Extractor) -> Self {
+        Self {
+            model: extractor.model().name(),
+            dim: extractor.feature_size(),
+            preprocess: extractor.preprocessor().version(),
+            metric: Metric::default(),
+        }
+    }
+
+    /// The fields of `found` that differ from `self`.
+    pub fn diff(&self, found: &Self) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        let mut check = |field, expected: String, found: String| {
+            if expected != found {
+                mismatches.push(Mismatch {
+                    field,
+                    expected,
+                    found,
+                });
+            }
+        };
+        check("model", self.model.clone(), found.model.clone());
+        check("dim", self.dim.to_string(), found.dim.to_string());
+        check(
+            "preprocess",
+            self.preprocess.clone(),
+            found.preprocess.clone(),
+        );
+        check(
+            "metric",
+            format!("{:?}", self.metric),
+            format!("{:?}", found.metric),
+        );
+        mismatches
+    }
+}
+
+/// A [`CollectionMeta`] as stored. Records written before models had names
+/// carry a [`NetworkKind`] under `kind` instead.
+#[derive(Deserialize)]
+struct StoredMeta {
+    model: Option<String>,
+    kind: Option<NetworkKind>,
+    dim: usize,
+    preprocess: String,
+    metric: Metric,
+}
+
+impl From<StoredMeta> for CollectionMeta {
+    fn from(stored: StoredMeta) -> Self {
+        let model = stored
+            .model
+            .or_else(|| stored.kind.map(|kind| kind.model_filename()))
+            .unwrap_or_default();
+        Self {
+            model,
+            dim: stored.dim,
+            preprocess: stored.preprocess,
+            metric: stored.metric,
+        }
+    }
+}
+
+/// One field of a [`CollectionMeta`] that does not match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub field: &'static str,
+    pub expected: String,
+    pub found: String,
+}
+
+/// A collection that was built with different settings than the running
+/// app, see [`Error::SchemaMismatch`].
+#[derive(Debug, Clone)]
+pub struct SchemaMismatch {
+    pub collection: String,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl fmt::Display for SchemaMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "collection `{}`", self.collection)?;
+        for (i, mismatch) in self.mismatches.iter().enumerate() {
+            let sep = if i == 0 { ":" } else { "," };
+            write!(
+                f,
+                "{sep} {} is {}, expected {}",
+                mismatch.field, mismatch.found, mismatch.expected
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the record of `store` against `expected`, writing it if the
+/// collection is still empty. A collection filled before records existed
+/// holds points of an unknown pipeline and is refused with
+/// [`Error::UnrecordedCollection`]; re-embed it into a new collection with
+/// [`App::migrate_from`](crate::App::migrate_from).
+pub async fn verify<S: VectorStore>(
+    store: &S,
+    collection: &str,
+    expected: &CollectionMeta,
+) -> Result<()> {
+    let Some(found) = store.metadata().await? else {
+        let sample = store.scroll::<serde::de::IgnoredAny>(None, 1).await?;
+        return if sample.items.is_empty() {
+            store.set_metadata(expected).await
+        } else {
+            Err(Error::UnrecordedCollection(format!(
+                "`{collection}` holds points but no schema record, \
+                 re-embed them into a new collection with `App::migrate_from`"
+            )))
+        };
+    };
+    let mismatches = expected.diff(&found);
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::SchemaMismatch(SchemaMismatch {
+            collection: collection.to_string(),
+            mismatches,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ImageInfo, store::MemoryStore};
+
+    fn meta(kind: NetworkKind, dim: usize) -> CollectionMeta {
+        CollectionMeta {
+            model: kind.model_filename(),
+            dim,
+            preprocess: "v1".to_string(),
+            metric: Metric::Cosine,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify_writes_then_checks() {
+        let store = MemoryStore::new(960);
+        let expected = meta(NetworkKind::Small, 960);
+        verify(&store, "images", &expected).await.unwrap();
+        assert_eq!(store.metadata().await.unwrap(), Some(expected.clone()));
+        verify(&store, "images", &expected).await.unwrap();
+
+        let err = verify(&store, "images", &meta(NetworkKind::Large, 960))
+            .await
+            .unwrap_err();
+        let Error::SchemaMismatch(mismatch) = err else {
+            panic!("unexpected error {err}");
+        };
+        assert_eq!(mismatch.mismatches.len(), 1);
+        assert_eq!(mismatch.mismatches[0].field, "model");
+        assert_eq!(
+            mismatch.to_string(),
+            format!(
+                "collection `images`: model is {}, expected {}",
+                NetworkKind::Small.model_filename(),
+                NetworkKind::Large.model_filename()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_refuses_unrecorded_collections() {
+        let store = MemoryStore::new(2);
+        let expected = meta(NetworkKind::Small, 2);
+        let info = ImageInfo::<()>::new("a", "a.png", None).with_preprocess(&expected.preprocess);
+        store.add(&[vec![1.0, 0.0]], &[info]).await.unwrap();
+
+        let err = verify(&store, "images", &expected).await.unwrap_err();
+        assert!(matches!(err, Error::UnrecordedCollection(_)));
+        assert!(err.to_string().contains("migrate_from"));
+        assert_eq!(store.metadata().await.unwrap(), None);
+    }
+
+    #[test]
+    fn test_reads_network_kind_records() {
+        let mut record = serde_json::to_value(meta(NetworkKind::Small, 960)).unwrap();
+        let record = record.as_object_mut().unwrap();
+        record.remove("model");
+        record.insert("kind".to_string(), "small".into());
+        let meta: CollectionMeta = serde_json::from_value(record.clone().into()).unwrap();
+        assert_eq!(meta.model, NetworkKind::Small.model_filename());
+    }
+}
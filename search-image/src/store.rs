@@ -0,0 +1,394 @@
+mod embedded;
+mod hnsw;
+mod memory;
+mod qdrant;
+
+pub use embedded::EmbeddedStore;
+pub use hnsw::{HnswIndex, Vectors};
+pub use memory::MemoryStore;
+pub use qdrant::{Generation, QdrantStore};
+
+use crate::{
+    app::{ImageInfo, SearchHit},
+    config::{Backend, DbConfig, IndexKind, Metric},
+    error::{Error, Result},
+    filter::PayloadFilter,
+    schema::CollectionMeta,
+};
+use serde::{Serialize, de::DeserializeOwned};
+use std::future::Future;
+
+/// One page of a [`VectorStore::scroll`] walk over a collection.
+#[derive(Debug, Clone)]
+pub struct ScrollPage<T = ()> {
+    pub items: Vec<ImageInfo<T>>,
+    /// Offset to pass to the next `scroll` call, `None` once the walk is done.
+    pub next_offset: Option<String>,
+}
+
+/// How a [`VectorStore::search_with`] query is run. Only `limit`, `offset`,
+/// `score_threshold`, `exact` and `filter` apply to every backend, the HNSW
+/// and quantization settings are hints the embedded and memory stores ignore
+/// where they have nothing to tune.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchOptions {
+    limit: usize,
+    offset: usize,
+    score_threshold: Option<f32>,
+    hnsw_ef: Option<usize>,
+    exact: bool,
+    quantization: Option<QuantizationSearch>,
+    filter: Option<PayloadFilter>,
+}
+
+impl SearchOptions {
+    /// Returns the `limit` best hits, without any other setting.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            offset: 0,
+            score_threshold: None,
+            hnsw_ef: None,
+            exact: false,
+            quantization: None,
+            filter: None,
+        }
+    }
+
+    /// Skips the `offset` best hits, for paging through results.
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Leaves out hits less similar than `threshold`: scoring below it for
+    /// the similarity metrics, above it for the distance ones, see
+    /// [`Metric::is_similarity`].
+    pub fn with_score_threshold(mut self, threshold: f32) -> Self {
+        self.score_threshold = Some(threshold);
+        self
+    }
+
+    /// Candidates kept while walking the HNSW graph, higher is slower but
+    /// more accurate. Falls back to the index setting when unset.
+    pub fn with_hnsw_ef(mut self, ef: usize) -> Self {
+        self.hnsw_ef = Some(ef);
+        self
+    }
+
+    /// Scores every point instead of using the index.
+    pub fn with_exact(mut self, exact: bool) -> Self {
+        self.exact = exact;
+        self
+    }
+
+    pub fn with_quantization(mut self, quantization: QuantizationSearch) -> Self {
+        self.quantization = Some(quantization);
+        self
+    }
+
+    /// Only considers points whose payload matches `filter`.
+    pub fn with_filter(mut self, filter: PayloadFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn score_threshold(&self) -> Option<f32> {
+        self.score_threshold
+    }
+
+    pub fn hnsw_ef(&self) -> Option<usize> {
+        self.hnsw_ef
+    }
+
+    pub fn exact(&self) -> bool {
+        self.exact
+    }
+
+    pub fn quantization(&self) -> Option<QuantizationSearch> {
+        self.quantization
+    }
+
+    pub fn filter(&self) -> Option<&PayloadFilter> {
+        self.filter.as_ref()
+    }
+
+    /// Whether `payload` passes the filter, if there is one.
+    pub(crate) fn admits(&self, payload: &serde_json::Value) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter.matches(payload))
+    }
+
+    /// Keeps the hits of a best-first `scored` list this query asks for.
+    pub(crate) fn select<I>(&self, scored: impl IntoIterator<Item = (f32, I)>) -> Vec<(f32, I)> {
+        scored
+            .into_iter()
+            .take_while(|(score, _)| self.score_threshold.is_none_or(|min| *score >= min))
+            .skip(self.offset)
+            .take(self.limit)
+            .collect()
+    }
+}
+
+/// How Qdrant uses quantized vectors for a search, see
+/// [`Quantization`](crate::config::Quantization).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QuantizationSearch {
+    ignore: bool,
+    rescore: Option<bool>,
+    oversampling: Option<f64>,
+}
+
+impl QuantizationSearch {
+    /// Searches the original vectors only.
+    pub fn with_ignore(mut self, ignore: bool) -> Self {
+        self.ignore = ignore;
+        self
+    }
+
+    /// Re-scores the candidates found with quantized vectors against the
+    /// original ones.
+    pub fn with_rescore(mut self, rescore: bool) -> Self {
+        self.rescore = Some(rescore);
+        self
+    }
+
+    /// Fetches `oversampling` times the limit from the quantized vectors
+    /// before rescoring.
+    pub fn with_oversampling(mut self, oversampling: f64) -> Self {
+        self.oversampling = Some(oversampling);
+        self
+    }
+
+    pub fn ignore(&self) -> bool {
+        self.ignore
+    }
+
+    pub fn rescore(&self) -> Option<bool> {
+        self.rescore
+    }
+
+    pub fn oversampling(&self) -> Option<f64> {
+        self.oversampling
+    }
+}
+
+/// Storage backend for image features and their `ImageInfo` payloads.
+pub trait VectorStore: Send + Sync {
+    fn add<T: Serialize + Sync>(
+        &self,
+        data: &[Vec<f32>],
+        image_info: &[ImageInfo<T>],
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn delete(&self, ids: &[String]) -> impl Future<Output = Result<()>> + Send;
+
+    /// Deletes every point whose payload matches `filter`. Fails with
+    /// [`Error::DeletePointsError`] on an empty filter rather than deleting
+    /// everything.
+    fn delete_where(&self, filter: &PayloadFilter) -> impl Future<Output = Result<()>> + Send;
+
+    fn get<T: DeserializeOwned + Send>(
+        &self,
+        ids: &[&str],
+    ) -> impl Future<Output = Result<Vec<ImageInfo<T>>>> + Send;
+
+    /// Returns the `k` points most similar to `feature`, best first.
+    fn search<T: DeserializeOwned + Send>(
+        &self,
+        feature: &[f32],
+        k: usize,
+    ) -> impl Future<Output = Result<Vec<SearchHit<T>>>> + Send {
+        self.search_with(feature, SearchOptions::new(k))
+    }
+
+    fn search_with<T: DeserializeOwned + Send>(
+        &self,
+        feature: &[f32],
+        options: SearchOptions,
+    ) -> impl Future<Output = Result<Vec<SearchHit<T>>>> + Send;
+
+    fn scroll<T: DeserializeOwned + Send>(
+        &self,
+        offset: Option<&str>,
+        limit: usize,
+    ) -> impl Future<Output = Result<ScrollPage<T>>> + Send {
+        self.scroll_with(offset, limit, None)
+    }
+
+    /// Like [`VectorStore::scroll`], skipping points that do not match
+    /// `filter`.
+    fn scroll_with<T: DeserializeOwned + Send>(
+        &self,
+        offset: Option<&str>,
+        limit: usize,
+        filter: Option<&PayloadFilter>,
+    ) -> impl Future<Output = Result<ScrollPage<T>>> + Send;
+
+    /// How the store scores hits. Only Qdrant supports anything but cosine.
+    fn metric(&self) -> Metric {
+        Metric::Cosine
+    }
+
+    /// The record describing the collection's vectors, `None` if none was
+    /// written yet.
+    fn metadata(&self) -> impl Future<Output = Result<Option<CollectionMeta>>> + Send;
+
+    fn set_metadata(&self, meta: &CollectionMeta) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// The backend selected by [`DbConfig::backend`].
+pub enum Store {
+    Qdrant(QdrantStore),
+    Embedded(EmbeddedStore),
+    Memory(MemoryStore),
+}
+
+impl Store {
+    /// Opens the backend of `db_config` for vectors of size `dim` produced by
+    /// the model named `model`.
+    pub async fn open(db_config: &DbConfig, dim: usize, model: &str) -> Result<Self> {
+        let metric = db_config.params().distance();
+        if db_config.backend() != Backend::Qdrant && metric != Metric::Cosine {
+            return Err(Error::ConfigError(format!(
+                "the {:?} backend only supports cosine similarity, not {metric:?}",
+                db_config.backend()
+            )));
+        }
+        match db_config.backend() {
+            Backend::Qdrant => Ok(Self::Qdrant(QdrantStore::connect(db_config, dim).await?)),
+            Backend::Embedded => {
+                let store = match db_config.index() {
+                    IndexKind::Flat => {
+                        EmbeddedStore::open(db_config.path(), db_config.collection(), dim, model)?
+                    }
+                    IndexKind::Hnsw => EmbeddedStore::open_hnsw(
+                        db_config.path(),
+                        db_config.collection(),
+                        dim,
+                        model,
+                        db_config.hnsw(),
+                    )?,
+                };
+                Ok(Self::Embedded(store))
+            }
+            Backend::Memory => Ok(Self::Memory(MemoryStore::new(dim))),
+        }
+    }
+
+    pub fn as_qdrant(&self) -> Option<&QdrantStore> {
+        match self {
+            Self::Qdrant(store) => Some(store),
+            _ => None,
+        }
+    }
+}
+
+impl VectorStore for Store {
+    fn metric(&self) -> Metric {
+        match self {
+            Self::Qdrant(store) => store.metric(),
+            Self::Embedded(store) => store.metric(),
+            Self::Memory(store) => store.metric(),
+        }
+    }
+
+    async fn add<T: Serialize + Sync>(
+        &self,
+        data: &[Vec<f32>],
+        image_info: &[ImageInfo<T>],
+    ) -> Result<()> {
+        match self {
+            Self::Qdrant(store) => store.add(data, image_info).await,
+            Self::Embedded(store) => store.add(data, image_info).await,
+            Self::Memory(store) => store.add(data, image_info).await,
+        }
+    }
+
+    async fn delete(&self, ids: &[String]) -> Result<()> {
+        match self {
+            Self::Qdrant(store) => store.delete(ids).await,
+            Self::Embedded(store) => store.delete(ids).await,
+            Self::Memory(store) => store.delete(ids).await,
+        }
+    }
+
+    async fn delete_where(&self, filter: &PayloadFilter) -> Result<()> {
+        match self {
+            Self::Qdrant(store) => store.delete_where(filter).await,
+            Self::Embedded(store) => store.delete_where(filter).await,
+            Self::Memory(store) => store.delete_where(filter).await,
+        }
+    }
+
+    async fn get<T: DeserializeOwned + Send>(&self, ids: &[&str]) -> Result<Vec<ImageInfo<T>>> {
+        match self {
+            Self::Qdrant(store) => store.get(ids).await,
+            Self::Embedded(store) => store.get(ids).await,
+            Self::Memory(store) => store.get(ids).await,
+        }
+    }
+
+    async fn search_with<T: DeserializeOwned + Send>(
+        &self,
+        feature: &[f32],
+        options: SearchOptions,
+    ) -> Result<Vec<SearchHit<T>>> {
+        match self {
+            Self::Qdrant(store) => store.search_with(feature, options).await,
+            Self::Embedded(store) => store.search_with(feature, options).await,
+            Self::Memory(store) => store.search_with(feature, options).await,
+        }
+    }
+
+    async fn scroll_with<T: DeserializeOwned + Send>(
+        &self,
+        offset: Option<&str>,
+        limit: usize,
+        filter: Option<&PayloadFilter>,
+    ) -> Result<ScrollPage<T>> {
+        match self {
+            Self::Qdrant(store) => store.scroll_with(offset, limit, filter).await,
+            Self::Embedded(store) => store.scroll_with(offset, limit, filter).await,
+            Self::Memory(store) => store.scroll_with(offset, limit, filter).await,
+        }
+    }
+
+    async fn metadata(&self) -> Result<Option<CollectionMeta>> {
+        match self {
+            Self::Qdrant(store) => store.metadata().await,
+            Self::Embedded(store) => store.metadata().await,
+            Self::Memory(store) => store.metadata().await,
+        }
+    }
+
+    async fn set_metadata(&self, meta: &CollectionMeta) -> Result<()> {
+        match self {
+            Self::Qdrant(store) => store.set_metadata(meta).await,
+            Self::Embedded(store) => store.set_metadata(meta).await,
+            Self::Memory(store) => store.set_metadata(meta).await,
+        }
+    }
+}
+
+/// Scales `vector` to unit length so cosine similarity becomes a dot product.
+pub(crate) fn normalize(vector: &[f32]) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return vector.to_vec();
+    }
+    vector.iter().map(|x| x / norm).collect()
+}
+
+pub(crate) fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
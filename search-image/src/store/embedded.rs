@@ -0,0 +1,761 @@
+use super::{HnswIndex, ScrollPage, SearchOptions, VectorStore, Vectors, normalize};
+use crate::{
+    app::{ImageInfo, SearchHit},
+    config::HnswConfig,
+    error::{Error, Result},
+    filter::PayloadFilter,
+    schema::CollectionMeta,
+};
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+const MAGIC: &[u8; 8] = b"SIMGVECS";
+const VERSION: u32 = 2;
+const HEADER_SIZE: u64 = 32;
+
+/// One line of the payload sidecar. Vectors are only ever appended, so an
+/// update is a `Put` pointing at a new slot and the old slot becomes garbage.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum LogRecord {
+    Put {
+        id: String,
+        slot: u64,
+        payload: serde_json::Value,
+    },
+    Delete {
+        id: String,
+    },
+}
+
+struct Entry {
+    slot: u64,
+    payload: serde_json::Value,
+}
+
+struct Inner {
+    vectors: File,
+    log: File,
+    mmap: Mmap,
+    slots: u64,
+    points: BTreeMap<String, Entry>,
+    hnsw: Option<HnswIndex>,
+    slot_ids: HashMap<u64, String>,
+    dirty: bool,
+}
+
+/// [`VectorStore`] kept in up to four files under a directory:
+///
+/// - `<collection>.vec`: a 32 byte header (magic, format version, vector size
+///   and a hash of the model name) followed by fixed-size little-endian `f32`
+///   records. The file is memory-mapped and scanned for searches.
+/// - `<collection>.jsonl`: an append-only log of payload puts and deletes.
+/// - `<collection>.meta.json`: the [`CollectionMeta`] record.
+/// - `<collection>.hnsw`: the cached [`HnswIndex`], only with
+///   [`EmbeddedStore::open_hnsw`].
+///
+/// Vectors are written and synced before the payload line that references
+/// them, so a crash can at worst leave an unreferenced vector or a torn last
+/// line, both of which are discarded on the next open.
+///
+/// When opened with [`EmbeddedStore::open_hnsw`], searches go through an
+/// [`HnswIndex`] whose graph is cached in `<collection>.hnsw`; it scores
+/// through the mapped vector file, so vectors are only stored once. The
+/// cache is written by [`EmbeddedStore::flush`] and on drop; vectors
+/// appended after the last flush are re-inserted on the next open, so the
+/// vector file stays the source of truth.
+pub struct EmbeddedStore {
+    dim: usize,
+    hnsw_path: PathBuf,
+    meta_path: PathBuf,
+    inner: RwLock<Inner>,
+}
+
+impl EmbeddedStore {
+    /// Opens a store answering searches with an exact scan.
+    pub fn open(dir: impl AsRef<Path>, collection: &str, dim: usize, model: &str) -> Result<Self> {
+        Self::open_with_index(dir.as_ref(), collection, dim, model, None)
+    }
+
+    /// Opens a store answering searches through an HNSW graph.
+    pub fn open_hnsw(
+        dir: impl AsRef<Path>,
+        collection: &str,
+        dim: usize,
+        model: &str,
+        config: HnswConfig,
+    ) -> Result<Self> {
+        Self::open_with_index(dir.as_ref(), collection, dim, model, Some(config))
+    }
+
+    fn open_with_index(
+        dir: &Path,
+        collection: &str,
+        dim: usize,
+        model: &str,
+        hnsw: Option<HnswConfig>,
+    ) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let mut vectors = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join(format!("{collection}.vec")))?;
+        let mut log = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join(format!("{collection}.jsonl")))?;
+
+        let record_size = (dim * 4) as u64;
+        let len = vectors.metadata()?.len();
+        if len == 0 {
+            vectors.write_all(&header(dim, model))?;
+            vectors.sync_all()?;
+        } else {
+            check_header(&mut vectors, dim, model)?;
+        }
+        let len = vectors.metadata()?.len();
+        let slots = (len - HEADER_SIZE) / record_size;
+        if HEADER_SIZE + slots * record_size != len {
+            vectors.set_len(HEADER_SIZE + slots * record_size)?;
+            vectors.sync_all()?;
+        }
+
+        let points = replay_log(&mut log, slots)?;
+        // SAFETY: the store owns the file for its lifetime; it is only
+        // written by `add`, which remaps afterwards, and never truncated
+        // below the mapped slots.
+        let mmap = unsafe { Mmap::map(&vectors)? };
+        let slot_ids = points
+            .iter()
+            .map(|(id, entry)| (entry.slot, id.clone()))
+            .collect::<HashMap<_, _>>();
+        let hnsw_path = dir.join(format!("{collection}.hnsw"));
+        let mut store = Self {
+            dim,
+            hnsw_path,
+            meta_path: dir.join(format!("{collection}.meta.json")),
+            inner: RwLock::new(Inner {
+                vectors,
+                log,
+                mmap,
+                slots,
+                points,
+                hnsw: None,
+                slot_ids,
+                dirty: false,
+            }),
+        };
+        if let Some(config) = hnsw {
+            store.build_hnsw(config)?;
+        }
+        Ok(store)
+    }
+
+    /// Loads the cached graph if it is usable, then brings it up to date with
+    /// the vector file and the live set from the payload log.
+    fn build_hnsw(&mut self, config: HnswConfig) -> Result<()> {
+        let inner = self.inner.get_mut().unwrap();
+        let cached = match HnswIndex::load(&self.hnsw_path) {
+            Ok(index) if index.dim() == self.dim && index.node_count() as u64 <= inner.slots => {
+                Some(index)
+            }
+            _ => None,
+        };
+        inner.dirty = cached.is_none();
+        let mut index = cached.unwrap_or_else(|| HnswIndex::new(self.dim, config));
+        index.set_ef_search(config.ef_search());
+
+        let slots = Slots::new(&inner.mmap, self.dim, inner.slots);
+        for _ in index.node_count() as u64..inner.slots {
+            index.insert(&slots)?;
+            inner.dirty = true;
+        }
+        let live = inner
+            .points
+            .values()
+            .map(|entry| entry.slot)
+            .collect::<HashSet<_>>();
+        for slot in 0..inner.slots {
+            if !live.contains(&slot) && index.delete(slot as u32) {
+                inner.dirty = true;
+            }
+        }
+        inner.hnsw = Some(index);
+        Ok(())
+    }
+
+    /// Writes the HNSW graph cache if it changed since the last flush.
+    pub fn flush(&self) -> Result<()> {
+        let mut inner = self.inner.write().unwrap();
+        if let Some(index) = inner.hnsw.as_ref().filter(|_| inner.dirty) {
+            index.save(&self.hnsw_path)?;
+            inner.dirty = false;
+        }
+        Ok(())
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.read().unwrap().points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Drop for EmbeddedStore {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// The vector file as the [`HnswIndex`] sees it, one node per slot.
+struct Slots<'a> {
+    mmap: &'a Mmap,
+    dim: usize,
+    slots: u64,
+}
+
+impl<'a> Slots<'a> {
+    fn new(mmap: &'a Mmap, dim: usize, slots: u64) -> Self {
+        Self { mmap, dim, slots }
+    }
+}
+
+impl Vectors for Slots<'_> {
+    fn len(&self) -> usize {
+        self.slots as usize
+    }
+
+    fn get(&self, node: u32) -> Cow<'_, [f32]> {
+        Cow::Owned(read_vector(self.mmap, self.dim, node as u64).collect())
+    }
+
+    fn score(&self, query: &[f32], node: u32) -> f32 {
+        read_vector(self.mmap, self.dim, node as u64)
+            .zip(query)
+            .map(|(x, y)| x * y)
+            .sum()
+    }
+}
+
+fn read_vector(mmap: &Mmap, dim: usize, slot: u64) -> impl Iterator<Item = f32> + '_ {
+    let start = (HEADER_SIZE + slot * (dim * 4) as u64) as usize;
+    mmap[start..start + dim * 4]
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn header(dim: usize, model: &str) -> [u8; HEADER_SIZE as usize] {
+    let mut header = [0u8; HEADER_SIZE as usize];
+    header[..8].copy_from_slice(MAGIC);
+    header[8..12].copy_from_slice(&VERSION.to_le_bytes());
+    header[12..16].copy_from_slice(&(dim as u32).to_le_bytes());
+    header[16..20].copy_from_slice(&model_code(model).to_le_bytes());
+    header
+}
+
+/// 32-bit FNV-1a hash of the model name, enough to tell models apart.
+fn model_code(model: &str) -> u32 {
+    model.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn check_header(file: &mut File, dim: usize, model: &str) -> Result<()> {
+    let mut found = [0u8; HEADER_SIZE as usize];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut found)
+        .map_err(|_| Error::IndexError("vector file header is truncated".to_string()))?;
+    if &found[..8] != MAGIC {
+        return Err(Error::IndexError("not a vector file".to_string()));
+    }
+    let expected = header(dim, model);
+    if found[8..12] != expected[8..12] {
+        return Err(Error::IndexError(format!(
+            "unsupported vector file version {}",
+            u32::from_le_bytes([found[8], found[9], found[10], found[11]])
+        )));
+    }
+    if found[12..16] != expected[12..16] {
+        return Err(Error::IndexError(format!(
+            "vector file holds vectors of size {}, expected {dim}",
+            u32::from_le_bytes([found[12], found[13], found[14], found[15]])
+        )));
+    }
+    if found[16..20] != expected[16..20] {
+        return Err(Error::IndexError(format!(
+            "vector file was built with a different model than {model}"
+        )));
+    }
+    Ok(())
+}
+
+/// Rebuilds the id -> slot map from the payload log, cutting off a torn tail.
+fn replay_log(log: &mut File, slots: u64) -> Result<BTreeMap<String, Entry>> {
+    let mut content = Vec::new();
+    log.seek(SeekFrom::Start(0))?;
+    log.read_to_end(&mut content)?;
+
+    let mut points = BTreeMap::new();
+    let mut valid = 0;
+    while let Some(end) = content[valid..].iter().position(|&b| b == b'\n') {
+        let line = &content[valid..valid + end];
+        match serde_json::from_slice::<LogRecord>(line) {
+            Ok(LogRecord::Put { id, slot, payload }) if slot < slots => {
+                points.insert(id, Entry { slot, payload });
+            }
+            Ok(LogRecord::Delete { id }) => {
+                points.remove(&id);
+            }
+            _ => break,
+        }
+        valid += end + 1;
+    }
+    if valid != content.len() {
+        log.set_len(valid as u64)?;
+        log.sync_all()?;
+    }
+    log.seek(SeekFrom::End(0))?;
+    Ok(points)
+}
+
+fn append_vectors(vectors: &mut File, offset: u64, bytes: &[u8]) -> Result<()> {
+    vectors.seek(SeekFrom::Start(offset))?;
+    vectors.write_all(bytes)?;
+    vectors.sync_data()?;
+    Ok(())
+}
+
+fn append_log(log: &mut File, records: &[LogRecord]) -> Result<()> {
+    let mut buf = Vec::new();
+    for record in records {
+        serde_json::to_writer(&mut buf, record)?;
+        buf.push(b'\n');
+    }
+    log.write_all(&buf)?;
+    log.sync_data()?;
+    Ok(())
+}
+
+impl VectorStore for EmbeddedStore {
+    async fn add<T: Serialize + Sync>(
+        &self,
+        data: &[Vec<f32>],
+        image_info: &[ImageInfo<T>],
+    ) -> Result<()> {
+        if data.len() != image_info.len() {
+            return Err(Error::UpsertPointsError(
+                "`data` and `image_info` must have the same length".to_string(),
+            ));
+        }
+        if let Some(vector) = data.iter().find(|vector| vector.len() != self.dim) {
+            return Err(Error::UpsertPointsError(format!(
+                "expected a vector of size {}, got {}",
+                self.dim,
+                vector.len()
+            )));
+        }
+
+        let mut inner = self.inner.write().unwrap();
+        let mut bytes = Vec::with_capacity(data.len() * self.dim * 4);
+        for vector in data {
+            bytes.extend(normalize(vector).iter().flat_map(|x| x.to_le_bytes()));
+        }
+        let records = image_info
+            .iter()
+            .enumerate()
+            .map(|(i, info)| {
+                Ok(LogRecord::Put {
+                    id: info.id().to_string(),
+                    slot: inner.slots + i as u64,
+                    payload: serde_json::to_value(info)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Write at the first free slot rather than at the end of the file,
+        // which may hold the vectors of an append whose log write failed.
+        let inner = &mut *inner;
+        let end = HEADER_SIZE + inner.slots * (self.dim * 4) as u64;
+        let log_len = inner.log.metadata()?.len();
+        let appended = append_vectors(&mut inner.vectors, end, &bytes)
+            .and_then(|()| append_log(&mut inner.log, &records));
+        if let Err(error) = appended {
+            // Cut a partial append off, so the next one starts clean.
+            inner.vectors.set_len(end)?;
+            inner.log.set_len(log_len)?;
+            inner.log.seek(SeekFrom::End(0))?;
+            return Err(error);
+        }
+
+        inner.slots += data.len() as u64;
+        // SAFETY: the file is only written through this store, under the
+        // write lock held here, and never shrunk below the mapped slots.
+        inner.mmap = unsafe { Mmap::map(&inner.vectors)? };
+        let slots = Slots::new(&inner.mmap, self.dim, inner.slots);
+        for record in records {
+            if let LogRecord::Put { id, slot, payload } = record {
+                if let Some(index) = inner.hnsw.as_mut() {
+                    index.insert(&slots)?;
+                    inner.dirty = true;
+                }
+                inner.slot_ids.insert(slot, id.clone());
+                if let Some(old) = inner.points.insert(id, Entry { slot, payload }) {
+                    inner.slot_ids.remove(&old.slot);
+                    if let Some(index) = inner.hnsw.as_mut() {
+                        index.delete(old.slot as u32);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, ids: &[String]) -> Result<()> {
+        let mut inner = self.inner.write().unwrap();
+        let records = ids
+            .iter()
+            .map(|id| LogRecord::Delete { id: id.clone() })
+            .collect::<Vec<_>>();
+        append_log(&mut inner.log, &records)?;
+        let inner = &mut *inner;
+        for id in ids {
+            if let Some(old) = inner.points.remove(id) {
+                inner.slot_ids.remove(&old.slot);
+                if let Some(index) = inner.hnsw.as_mut() {
+                    index.delete(old.slot as u32);
+                    inner.dirty = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_where(&self, filter: &PayloadFilter) -> Result<()> {
+        filter.check_deletable()?;
+        let ids = {
+            let inner = self.inner.read().unwrap();
+            inner
+                .points
+                .iter()
+                .filter(|(_, entry)| filter.matches(&entry.payload))
+                .map(|(id, _)| id.clone())
+                .collect::<Vec<_>>()
+        };
+        self.delete(&ids).await
+    }
+
+    async fn get<T: DeserializeOwned + Send>(&self, ids: &[&str]) -> Result<Vec<ImageInfo<T>>> {
+        let inner = self.inner.read().unwrap();
+        ids.iter()
+            .filter_map(|id| inner.points.get(*id).map(|entry| (id, entry)))
+            .map(|(id, entry)| ImageInfo::from_payload(id.to_string(), entry.payload.clone()))
+            .collect()
+    }
+
+    async fn search_with<T: DeserializeOwned + Send>(
+        &self,
+        feature: &[f32],
+        options: SearchOptions,
+    ) -> Result<Vec<SearchHit<T>>> {
+        if feature.len() != self.dim {
+            return Err(Error::SearchPointsError(format!(
+                "expected a query of size {}, got {}",
+                self.dim,
+                feature.len()
+            )));
+        }
+        let inner = self.inner.read().unwrap();
+        if let Some(index) = inner.hnsw.as_ref().filter(|_| !options.exact()) {
+            let wanted = options.offset() + options.limit();
+            let ef = options.hnsw_ef().unwrap_or(index.config().ef_search());
+            let slots = Slots::new(&inner.mmap, self.dim, inner.slots);
+            // The filter is applied to the graph's candidates, asking for
+            // twice as many each round until enough of them pass, the graph
+            // runs out or the rest would score below the threshold.
+            let mut k = wanted;
+            let scored = loop {
+                let found = index.search_ef(&slots, feature, k, ef);
+                let exhausted = found.len() < k || k >= index.len();
+                let below = found.last().is_some_and(|(_, score)| {
+                    options.score_threshold().is_some_and(|min| *score < min)
+                });
+                let scored = found
+                    .into_iter()
+                    .filter_map(|(node, score)| {
+                        let id = inner.slot_ids.get(&(node as u64))?;
+                        options
+                            .admits(&inner.points[id].payload)
+                            .then_some((score, id))
+                    })
+                    .collect::<Vec<_>>();
+                if scored.len() >= wanted || exhausted || below {
+                    break scored;
+                }
+                k *= 2;
+            };
+            return options
+                .select(scored)
+                .into_iter()
+                .map(|(score, id)| {
+                    let info =
+                        ImageInfo::from_payload(id.clone(), inner.points[id].payload.clone())?;
+                    Ok(SearchHit::new(info, score))
+                })
+                .collect();
+        }
+
+        let query = normalize(feature);
+        let mut scored = inner
+            .points
+            .iter()
+            .filter(|(_, entry)| options.admits(&entry.payload))
+            .map(|(id, entry)| {
+                let score = read_vector(&inner.mmap, self.dim, entry.slot)
+                    .zip(&query)
+                    .map(|(x, y)| x * y)
+                    .sum::<f32>();
+                (score, (id, entry))
+            })
+            .collect::<Vec<_>>();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        options
+            .select(scored)
+            .into_iter()
+            .map(|(score, (id, entry))| {
+                let info = ImageInfo::from_payload(id.clone(), entry.payload.clone())?;
+                Ok(SearchHit::new(info, score))
+            })
+            .collect()
+    }
+
+    async fn scroll_with<T: DeserializeOwned + Send>(
+        &self,
+        offset: Option<&str>,
+        limit: usize,
+        filter: Option<&PayloadFilter>,
+    ) -> Result<ScrollPage<T>> {
+        let inner = self.inner.read().unwrap();
+        let range = match offset {
+            Some(offset) => inner.points.range(offset.to_string()..),
+            None => inner.points.range::<String, _>(..),
+        };
+        let mut range =
+            range.filter(|(_, entry)| filter.is_none_or(|filter| filter.matches(&entry.payload)));
+        let items = range
+            .by_ref()
+            .take(limit)
+            .map(|(id, entry)| ImageInfo::from_payload(id.clone(), entry.payload.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        let next_offset = range.next().map(|(id, _)| id.clone());
+        Ok(ScrollPage { items, next_offset })
+    }
+
+    async fn metadata(&self) -> Result<Option<CollectionMeta>> {
+        match std::fs::read(&self.meta_path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn set_metadata(&self, meta: &CollectionMeta) -> Result<()> {
+        let tmp = self.meta_path.with_extension("json.tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&serde_json::to_vec_pretty(meta)?)?;
+        // A torn record would be refused, or rewritten over existing data.
+        file.sync_all()?;
+        std::fs::rename(&tmp, &self.meta_path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        App, app::tests::images, extractor::Extractor, model::tests::MeanColor,
+        utils::tests::TempDir,
+    };
+    use candle_core::Device;
+    use std::sync::Arc;
+
+    fn temp_dir(name: &str) -> TempDir {
+        TempDir::new(&format!("embedded-{name}"))
+    }
+
+    fn info(id: &str) -> ImageInfo<()> {
+        ImageInfo::new(id, &format!("{id}.png"), None)
+    }
+
+    #[tokio::test]
+    async fn test_reopen_keeps_points() {
+        let dir = temp_dir("reopen");
+        {
+            let store = EmbeddedStore::open(&dir, "images", 2, "small").unwrap();
+            store
+                .add(&[vec![1.0, 0.0], vec![0.0, 1.0]], &[info("a"), info("b")])
+                .await
+                .unwrap();
+            store.delete(&["b".to_string()]).await.unwrap();
+        }
+        let store = EmbeddedStore::open(&dir, "images", 2, "small").unwrap();
+        assert_eq!(store.len(), 1);
+        let hits = store.search::<()>(&[1.0, 0.0], 5).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].id(), "a");
+        assert!((hits[0].score() - 1.0).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn test_torn_tail_is_discarded() {
+        let dir = temp_dir("torn");
+        {
+            let store = EmbeddedStore::open(&dir, "images", 2, "small").unwrap();
+            store.add(&[vec![1.0, 0.0]], &[info("a")]).await.unwrap();
+        }
+        let mut vectors = OpenOptions::new()
+            .append(true)
+            .open(dir.join("images.vec"))
+            .unwrap();
+        vectors.write_all(&[0, 0, 128]).unwrap();
+        let mut log = OpenOptions::new()
+            .append(true)
+            .open(dir.join("images.jsonl"))
+            .unwrap();
+        log.write_all(br#"{"op":"put","id":"b","slot":1,"pay"#)
+            .unwrap();
+
+        let store = EmbeddedStore::open(&dir, "images", 2, "small").unwrap();
+        assert_eq!(store.len(), 1);
+        store.add(&[vec![0.0, 1.0]], &[info("b")]).await.unwrap();
+        let hits = store.search::<()>(&[0.0, 1.0], 1).await.unwrap();
+        assert_eq!(hits[0].id(), "b");
+    }
+
+    #[tokio::test]
+    async fn test_add_overwrites_unreferenced_vectors() {
+        let dir = temp_dir("stray");
+        {
+            let store = EmbeddedStore::open(&dir, "images", 2, "small").unwrap();
+            store.add(&[vec![1.0, 0.0]], &[info("a")]).await.unwrap();
+            // What an append whose log write failed leaves behind.
+            let mut vectors = OpenOptions::new()
+                .append(true)
+                .open(dir.join("images.vec"))
+                .unwrap();
+            vectors.write_all(&[0; 8]).unwrap();
+            store.add(&[vec![0.0, 1.0]], &[info("b")]).await.unwrap();
+        }
+
+        let store = EmbeddedStore::open(&dir, "images", 2, "small").unwrap();
+        assert_eq!(store.len(), 2);
+        let hits = store.search::<()>(&[0.0, 1.0], 1).await.unwrap();
+        assert_eq!(hits[0].id(), "b");
+        assert!((hits[0].score() - 1.0).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn test_hnsw_survives_reopen() {
+        let dir = temp_dir("hnsw");
+        let config = HnswConfig::default();
+        {
+            let store = EmbeddedStore::open_hnsw(&dir, "images", 2, "small", config).unwrap();
+            store
+                .add(
+                    &[vec![1.0, 0.0], vec![0.0, 1.0], vec![1.0, 1.0]],
+                    &[info("a"), info("b"), info("c")],
+                )
+                .await
+                .unwrap();
+        }
+        assert!(dir.join("images.hnsw").exists());
+        let store = EmbeddedStore::open_hnsw(&dir, "images", 2, "small", config).unwrap();
+        store.add(&[vec![0.1, 1.0]], &[info("a")]).await.unwrap();
+        store.delete(&["b".to_string()]).await.unwrap();
+        let hits = store.search::<()>(&[0.0, 1.0], 3).await.unwrap();
+        assert_eq!(
+            hits.iter().map(|hit| hit.id()).collect::<Vec<_>>(),
+            ["a", "c"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_metadata_survives_reopen() {
+        let dir = temp_dir("meta");
+        let meta = CollectionMeta {
+            model: "small".to_string(),
+            dim: 2,
+            preprocess: "v1".to_string(),
+            metric: Default::default(),
+        };
+        {
+            let store = EmbeddedStore::open(&dir, "images", 2, "small").unwrap();
+            assert_eq!(store.metadata().await.unwrap(), None);
+            store.set_metadata(&meta).await.unwrap();
+        }
+        let store = EmbeddedStore::open(&dir, "images", 2, "small").unwrap();
+        assert_eq!(store.metadata().await.unwrap(), Some(meta));
+    }
+
+    #[test]
+    fn test_header_mismatch() {
+        let dir = temp_dir("header");
+        EmbeddedStore::open(&dir, "images", 2, "small").unwrap();
+        assert!(matches!(
+            EmbeddedStore::open(&dir, "images", 3, "small"),
+            Err(Error::IndexError(_))
+        ));
+        assert!(matches!(
+            EmbeddedStore::open(&dir, "images", 2, "large"),
+            Err(Error::IndexError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_app_search_goes_through_the_graph() {
+        let (_images, paths) = images();
+        let dir = temp_dir("app");
+        let store =
+            EmbeddedStore::open_hnsw(&dir, "images", 2, "mean", HnswConfig::default()).unwrap();
+        let extractor = Extractor::from_model(Arc::new(MeanColor), &Device::Cpu);
+        let app = App::with_store(store, extractor);
+        app.add_images(&paths).await.unwrap();
+
+        // Take the green image out of the graph only, a scan still finds it.
+        {
+            let mut inner = app.store().inner.write().unwrap();
+            let slot = inner
+                .points
+                .values()
+                .find(|entry| entry.payload["path"].as_str().unwrap().ends_with("c.png"))
+                .unwrap()
+                .slot;
+            inner.hnsw.as_mut().unwrap().delete(slot as u32);
+        }
+        let hits = app.search::<(), _>(&paths[2], 3).await.unwrap();
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().all(|hit| !hit.path().ends_with("c.png")));
+
+        let exact = SearchOptions::new(3).with_exact(true);
+        let hits = app.search_with::<(), _>(&paths[2], exact).await.unwrap();
+        assert!(hits[0].path().ends_with("c.png"));
+    }
+}
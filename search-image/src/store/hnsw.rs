@@ -0,0 +1,618 @@
+use super::{dot, normalize};
+use crate::{
+    config::HnswConfig,
+    error::{Error, Result},
+};
+use std::{
+    borrow::Cow,
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashSet},
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+const MAGIC: &[u8; 8] = b"SIMGHNSW";
+/// - 1: the graph followed by a copy of every vector.
+/// - 2: the graph only.
+const VERSION: u32 = 2;
+
+/// The unit-length vectors an [`HnswIndex`] is built over, node `n` being the
+/// `n`-th of them. The index only keeps the graph and scores through this, so
+/// the vectors are stored once, by their owner.
+pub trait Vectors {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, node: u32) -> Cow<'_, [f32]>;
+
+    /// Similarity of `query` with the vector of `node`.
+    fn score(&self, query: &[f32], node: u32) -> f32 {
+        dot(query, &self.get(node))
+    }
+}
+
+impl Vectors for [Vec<f32>] {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn get(&self, node: u32) -> Cow<'_, [f32]> {
+        Cow::Borrowed(&self[node as usize])
+    }
+}
+
+/// A node id paired with its similarity to the current query, ordered by
+/// similarity so a `BinaryHeap<Candidate>` pops the closest node first.
+#[derive(Clone, Copy)]
+struct Candidate {
+    score: f32,
+    node: u32,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then(self.node.cmp(&other.node))
+    }
+}
+
+/// Hierarchical navigable small world graph over cosine similarity.
+///
+/// Nodes are numbered in insertion order and read their vectors from the
+/// [`Vectors`] passed to each call, which must be the same every time.
+/// Deleted nodes stay in the graph as tombstones so that the paths through
+/// them keep working, they are only dropped from search results.
+pub struct HnswIndex {
+    config: HnswConfig,
+    dim: usize,
+    links: Vec<Vec<Vec<u32>>>,
+    deleted: Vec<bool>,
+    live: usize,
+    entry: Option<u32>,
+    rng: u64,
+}
+
+impl HnswIndex {
+    pub fn new(dim: usize, config: HnswConfig) -> Self {
+        Self {
+            config,
+            dim,
+            links: Vec::new(),
+            deleted: Vec::new(),
+            live: 0,
+            entry: None,
+            rng: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+
+    pub fn config(&self) -> HnswConfig {
+        self.config
+    }
+
+    pub fn set_ef_search(&mut self, ef_search: usize) {
+        self.config = HnswConfig::new(self.config.m(), self.config.ef_construction(), ef_search);
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Number of nodes, including tombstones.
+    pub fn node_count(&self) -> usize {
+        self.links.len()
+    }
+
+    /// Number of nodes that can be returned by [`HnswIndex::search`].
+    pub fn len(&self) -> usize {
+        self.live
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.live == 0
+    }
+
+    pub fn is_deleted(&self, node: u32) -> bool {
+        self.deleted.get(node as usize).copied().unwrap_or(true)
+    }
+
+    /// Adds the next node, whose vector is the one after the last node's in
+    /// `vectors`, to the graph and returns its id.
+    pub fn insert<V: Vectors + ?Sized>(&mut self, vectors: &V) -> Result<u32> {
+        let node = self.links.len() as u32;
+        if node as usize >= vectors.len() {
+            return Err(Error::IndexError(format!("no vector for node {node}")));
+        }
+        let query = vectors.get(node).into_owned();
+        if query.len() != self.dim {
+            return Err(Error::IndexError(format!(
+                "expected a vector of size {}, got {}",
+                self.dim,
+                query.len()
+            )));
+        }
+        let level = self.random_level();
+        self.links.push(vec![Vec::new(); level + 1]);
+        self.deleted.push(false);
+        self.live += 1;
+
+        let Some(entry) = self.entry else {
+            self.entry = Some(node);
+            return Ok(node);
+        };
+
+        let top = self.level(entry);
+        let mut ep = Candidate {
+            score: vectors.score(&query, entry),
+            node: entry,
+        };
+        for layer in (level + 1..=top).rev() {
+            ep = self.greedy(vectors, &query, ep, layer);
+        }
+
+        let mut eps = vec![ep];
+        for layer in (0..=level.min(top)).rev() {
+            let ef = self.config.ef_construction();
+            let found = self.search_layer(vectors, &query, &eps, ef, layer, false);
+            let neighbours = self.select_neighbours(vectors, &found, self.max_links(layer));
+            for &neighbour in &neighbours {
+                self.links[neighbour as usize][layer].push(node);
+                self.prune(vectors, neighbour, layer);
+            }
+            self.links[node as usize][layer] = neighbours;
+            eps = found;
+        }
+
+        if level > top {
+            self.entry = Some(node);
+        }
+        Ok(node)
+    }
+
+    /// Marks `node` as deleted. Returns `false` if it was already gone.
+    pub fn delete(&mut self, node: u32) -> bool {
+        match self.deleted.get_mut(node as usize) {
+            Some(deleted) if !*deleted => {
+                *deleted = true;
+                self.live -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns up to `k` live nodes most similar to `query`, best first.
+    pub fn search<V: Vectors + ?Sized>(
+        &self,
+        vectors: &V,
+        query: &[f32],
+        k: usize,
+    ) -> Vec<(u32, f32)> {
+        self.search_ef(vectors, query, k, self.config.ef_search())
+    }
+
+    /// Like [`HnswIndex::search`], keeping `ef` candidates instead of the
+    /// configured `ef_search`.
+    pub fn search_ef<V: Vectors + ?Sized>(
+        &self,
+        vectors: &V,
+        query: &[f32],
+        k: usize,
+        ef: usize,
+    ) -> Vec<(u32, f32)> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+        if query.len() != self.dim || k == 0 {
+            return Vec::new();
+        }
+        let query = normalize(query);
+        let mut ep = Candidate {
+            score: vectors.score(&query, entry),
+            node: entry,
+        };
+        for layer in (1..=self.level(entry)).rev() {
+            ep = self.greedy(vectors, &query, ep, layer);
+        }
+        self.search_layer(vectors, &query, &[ep], ef.max(k), 0, true)
+            .into_iter()
+            .take(k)
+            .map(|c| (c.node, c.score))
+            .collect()
+    }
+
+    /// Writes the graph to `path`, replacing any previous file atomically. The
+    /// vectors are not part of it.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        writer.write_all(MAGIC)?;
+        for value in [
+            VERSION,
+            self.dim as u32,
+            self.config.m() as u32,
+            self.config.ef_construction() as u32,
+            self.config.ef_search() as u32,
+            self.links.len() as u32,
+            self.entry.unwrap_or(u32::MAX),
+        ] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&self.rng.to_le_bytes())?;
+        for (links, deleted) in self.links.iter().zip(&self.deleted) {
+            writer.write_all(&[links.len() as u8, *deleted as u8])?;
+            for layer in links {
+                writer.write_all(&(layer.len() as u32).to_le_bytes())?;
+                for neighbour in layer {
+                    writer.write_all(&neighbour.to_le_bytes())?;
+                }
+            }
+        }
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Reads a graph written by [`HnswIndex::save`]. `ef_search` is taken from
+    /// the file and can be changed afterwards with [`HnswIndex::set_ef_search`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::IndexError("not an HNSW index file".to_string()));
+        }
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            return Err(Error::IndexError(format!(
+                "unsupported HNSW index version {version}"
+            )));
+        }
+        let dim = read_u32(&mut reader)? as usize;
+        let m = read_u32(&mut reader)? as usize;
+        let ef_construction = read_u32(&mut reader)? as usize;
+        let ef_search = read_u32(&mut reader)? as usize;
+        let nodes = read_u32(&mut reader)? as usize;
+        let entry = Some(read_u32(&mut reader)?).filter(|&entry| entry != u32::MAX);
+        let mut rng = [0u8; 8];
+        reader.read_exact(&mut rng)?;
+
+        let mut links = Vec::with_capacity(nodes);
+        let mut deleted = Vec::with_capacity(nodes);
+        for _ in 0..nodes {
+            let mut flags = [0u8; 2];
+            reader.read_exact(&mut flags)?;
+            let mut layers = Vec::with_capacity(flags[0] as usize);
+            for _ in 0..flags[0] {
+                let count = read_u32(&mut reader)? as usize;
+                layers.push(
+                    (0..count)
+                        .map(|_| read_u32(&mut reader))
+                        .collect::<Result<Vec<_>>>()?,
+                );
+            }
+            links.push(layers);
+            deleted.push(flags[1] != 0);
+        }
+        Ok(Self {
+            config: HnswConfig::new(m, ef_construction, ef_search),
+            dim,
+            links,
+            live: deleted.iter().filter(|deleted| !**deleted).count(),
+            deleted,
+            entry,
+            rng: u64::from_le_bytes(rng),
+        })
+    }
+
+    fn level(&self, node: u32) -> usize {
+        self.links[node as usize].len() - 1
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 {
+            self.config.m() * 2
+        } else {
+            self.config.m()
+        }
+    }
+
+    /// Draws a level from the usual exponential distribution with
+    /// `1 / ln(M)` normalisation, using a xorshift generator so that builds
+    /// are reproducible.
+    fn random_level(&mut self) -> usize {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        let uniform = ((self.rng >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+        let ml = 1.0 / (self.config.m().max(2) as f64).ln();
+        ((-uniform.ln() * ml) as usize).min(u8::MAX as usize - 1)
+    }
+
+    fn greedy<V: Vectors + ?Sized>(
+        &self,
+        vectors: &V,
+        query: &[f32],
+        mut best: Candidate,
+        layer: usize,
+    ) -> Candidate {
+        loop {
+            let mut improved = false;
+            for &neighbour in &self.links[best.node as usize][layer] {
+                let score = vectors.score(query, neighbour);
+                if score > best.score {
+                    best = Candidate {
+                        score,
+                        node: neighbour,
+                    };
+                    improved = true;
+                }
+            }
+            if !improved {
+                return best;
+            }
+        }
+    }
+
+    /// Best-first search restricted to one layer. Returns at most `ef`
+    /// candidates, best first. With `live_only`, deleted nodes are still
+    /// walked through but never returned, so they do not take the place of
+    /// live ones among the `ef` kept.
+    fn search_layer<V: Vectors + ?Sized>(
+        &self,
+        vectors: &V,
+        query: &[f32],
+        entry_points: &[Candidate],
+        ef: usize,
+        layer: usize,
+        live_only: bool,
+    ) -> Vec<Candidate> {
+        let keep = |c: &Candidate| !live_only || !self.deleted[c.node as usize];
+        let mut visited = entry_points.iter().map(|c| c.node).collect::<HashSet<_>>();
+        let mut candidates = entry_points.iter().copied().collect::<BinaryHeap<_>>();
+        let mut found = entry_points
+            .iter()
+            .copied()
+            .filter(keep)
+            .map(Reverse)
+            .collect::<BinaryHeap<_>>();
+        while found.len() > ef {
+            found.pop();
+        }
+
+        while let Some(current) = candidates.pop() {
+            let worst = found.peek().map(|c| c.0.score).unwrap_or(f32::MIN);
+            if current.score < worst && found.len() >= ef {
+                break;
+            }
+            let Some(links) = self.links[current.node as usize].get(layer) else {
+                continue;
+            };
+            for &neighbour in links {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                let candidate = Candidate {
+                    score: vectors.score(query, neighbour),
+                    node: neighbour,
+                };
+                let worst = found.peek().map(|c| c.0.score).unwrap_or(f32::MIN);
+                if found.len() < ef || candidate.score > worst {
+                    candidates.push(candidate);
+                    if keep(&candidate) {
+                        found.push(Reverse(candidate));
+                        if found.len() > ef {
+                            found.pop();
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut found = found.into_iter().map(|c| c.0).collect::<Vec<_>>();
+        found.sort_by(|a, b| b.cmp(a));
+        found
+    }
+
+    /// Neighbour selection heuristic from the HNSW paper: walking the
+    /// candidates best first, keep one only if it is closer to the base node
+    /// than to every neighbour kept so far. This spreads links across
+    /// directions and keeps clustered data connected. Leftover slots are
+    /// filled with the closest discarded candidates.
+    fn select_neighbours<V: Vectors + ?Sized>(
+        &self,
+        vectors: &V,
+        candidates: &[Candidate],
+        max: usize,
+    ) -> Vec<u32> {
+        let mut selected = Vec::with_capacity(max);
+        let mut discarded = Vec::new();
+        for candidate in candidates {
+            if selected.len() >= max {
+                break;
+            }
+            let vector = vectors.get(candidate.node);
+            let diverse = selected
+                .iter()
+                .all(|&kept| vectors.score(&vector, kept) < candidate.score);
+            if diverse {
+                selected.push(candidate.node);
+            } else {
+                discarded.push(candidate.node);
+            }
+        }
+        let missing = max.saturating_sub(selected.len());
+        selected.extend(discarded.into_iter().take(missing));
+        selected
+    }
+
+    /// Shrinks the links of `node` on `layer` back to `max_links`.
+    fn prune<V: Vectors + ?Sized>(&mut self, vectors: &V, node: u32, layer: usize) {
+        let max = self.max_links(layer);
+        if self.links[node as usize][layer].len() <= max {
+            return;
+        }
+        let base = vectors.get(node);
+        let mut scored = self.links[node as usize][layer]
+            .iter()
+            .map(|&neighbour| Candidate {
+                score: vectors.score(&base, neighbour),
+                node: neighbour,
+            })
+            .collect::<Vec<_>>();
+        scored.sort_by(|a, b| b.cmp(a));
+        self.links[node as usize][layer] = self.select_neighbours(vectors, &scored, max);
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TempDir;
+
+    /// `n` random unit vectors.
+    fn vectors(n: usize, dim: usize) -> Vec<Vec<f32>> {
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        (0..n)
+            .map(|_| {
+                let vector = (0..dim)
+                    .map(|_| {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        (state >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+                    })
+                    .collect::<Vec<_>>();
+                normalize(&vector)
+            })
+            .collect()
+    }
+
+    fn build(data: &[Vec<f32>], config: HnswConfig) -> HnswIndex {
+        let mut index = HnswIndex::new(data[0].len(), config);
+        for _ in data {
+            index.insert(data).unwrap();
+        }
+        index
+    }
+
+    fn exact(data: &[Vec<f32>], query: &[f32], k: usize) -> Vec<u32> {
+        let query = normalize(query);
+        let mut scored = data
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (dot(&query, v), i as u32))
+            .collect::<Vec<_>>();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.into_iter().take(k).map(|(_, i)| i).collect()
+    }
+
+    #[test]
+    fn test_recall_against_exact() {
+        let data = vectors(2000, 32);
+        let index = build(&data, HnswConfig::new(16, 200, 100));
+        let queries = vectors(50, 32);
+        let hits = queries
+            .iter()
+            .map(|q| {
+                let truth = exact(&data, q, 10);
+                index
+                    .search(&data[..], q, 10)
+                    .iter()
+                    .filter(|(node, _)| truth.contains(node))
+                    .count()
+            })
+            .sum::<usize>();
+        assert!(hits as f32 / 500.0 > 0.9, "recall {}", hits as f32 / 500.0);
+    }
+
+    #[test]
+    fn test_deleted_nodes_are_skipped() {
+        let data = vectors(200, 8);
+        let mut index = build(&data, HnswConfig::default());
+        let best = index.search(&data[..], &data[7], 1)[0].0;
+        assert_eq!(best, 7);
+        assert!(index.delete(7));
+        assert!(!index.delete(7));
+        assert_eq!(index.len(), 199);
+        assert!(
+            index
+                .search(&data[..], &data[7], 10)
+                .iter()
+                .all(|(node, _)| *node != 7)
+        );
+    }
+
+    #[test]
+    fn test_deleted_neighbourhood_still_fills_k() {
+        let data = vectors(300, 8);
+        let mut index = build(&data, HnswConfig::default());
+        for &node in &exact(&data, &data[0], 50) {
+            index.delete(node);
+        }
+        let hits = index.search_ef(&data[..], &data[0], 10, 10);
+        assert_eq!(hits.len(), 10);
+        assert!(hits.iter().all(|(node, _)| !index.is_deleted(*node)));
+    }
+
+    #[test]
+    fn test_insert_needs_the_next_vector() {
+        let data = vectors(2, 8);
+        let mut index = build(&data, HnswConfig::default());
+        assert!(matches!(index.insert(&data[..]), Err(Error::IndexError(_))));
+        assert!(matches!(
+            index.insert(&[data[0].clone(), data[1].clone(), vec![1.0]][..]),
+            Err(Error::IndexError(_))
+        ));
+        assert_eq!(index.node_count(), 2);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut data = vectors(300, 8);
+        let mut index = build(&data, HnswConfig::default());
+        index.delete(3);
+        let dir = TempDir::new("hnsw");
+        let path = dir.join("index.hnsw");
+        index.save(&path).unwrap();
+        let mut loaded = HnswIndex::load(&path).unwrap();
+
+        assert_eq!(loaded.node_count(), 300);
+        assert_eq!(loaded.len(), 299);
+        assert!(loaded.is_deleted(3));
+        assert_eq!(
+            loaded.search(&data[..], &data[42], 5),
+            index.search(&data[..], &data[42], 5)
+        );
+        data.push(data[0].clone());
+        assert_eq!(loaded.insert(&data[..]).unwrap(), 300);
+    }
+}
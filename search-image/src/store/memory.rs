@@ -0,0 +1,276 @@
+use super::{ScrollPage, SearchOptions, VectorStore, dot, normalize};
+use crate::{
+    app::{ImageInfo, SearchHit},
+    error::{Error, Result},
+    filter::PayloadFilter,
+    schema::CollectionMeta,
+};
+use serde::{Serialize, de::DeserializeOwned};
+use std::{collections::BTreeMap, sync::RwLock};
+
+struct Entry {
+    vector: Vec<f32>,
+    payload: serde_json::Value,
+}
+
+/// [`VectorStore`] that keeps every point in memory and answers searches with
+/// an exact brute-force cosine scan. Useful for tests and small collections.
+pub struct MemoryStore {
+    dim: usize,
+    points: RwLock<BTreeMap<String, Entry>>,
+    meta: RwLock<Option<CollectionMeta>>,
+}
+
+impl MemoryStore {
+    pub fn new(dim: usize) -> Self {
+        Self {
+            dim,
+            points: RwLock::new(BTreeMap::new()),
+            meta: RwLock::new(None),
+        }
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl VectorStore for MemoryStore {
+    async fn add<T: Serialize + Sync>(
+        &self,
+        data: &[Vec<f32>],
+        image_info: &[ImageInfo<T>],
+    ) -> Result<()> {
+        if data.len() != image_info.len() {
+            return Err(Error::UpsertPointsError(
+                "`data` and `image_info` must have the same length".to_string(),
+            ));
+        }
+        let entries = data
+            .iter()
+            .zip(image_info)
+            .map(|(vector, info)| {
+                if vector.len() != self.dim {
+                    return Err(Error::UpsertPointsError(format!(
+                        "expected a vector of size {}, got {}",
+                        self.dim,
+                        vector.len()
+                    )));
+                }
+                let entry = Entry {
+                    vector: normalize(vector),
+                    payload: serde_json::to_value(info)?,
+                };
+                Ok((info.id().to_string(), entry))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.points.write().unwrap().extend(entries);
+        Ok(())
+    }
+
+    async fn delete(&self, ids: &[String]) -> Result<()> {
+        let mut points = self.points.write().unwrap();
+        for id in ids {
+            points.remove(id);
+        }
+        Ok(())
+    }
+
+    async fn delete_where(&self, filter: &PayloadFilter) -> Result<()> {
+        filter.check_deletable()?;
+        self.points
+            .write()
+            .unwrap()
+            .retain(|_, entry| !filter.matches(&entry.payload));
+        Ok(())
+    }
+
+    async fn get<T: DeserializeOwned + Send>(&self, ids: &[&str]) -> Result<Vec<ImageInfo<T>>> {
+        let points = self.points.read().unwrap();
+        ids.iter()
+            .filter_map(|id| points.get(*id).map(|entry| (id, entry)))
+            .map(|(id, entry)| ImageInfo::from_payload(id.to_string(), entry.payload.clone()))
+            .collect()
+    }
+
+    async fn search_with<T: DeserializeOwned + Send>(
+        &self,
+        feature: &[f32],
+        options: SearchOptions,
+    ) -> Result<Vec<SearchHit<T>>> {
+        if feature.len() != self.dim {
+            return Err(Error::SearchPointsError(format!(
+                "expected a query of size {}, got {}",
+                self.dim,
+                feature.len()
+            )));
+        }
+        let query = normalize(feature);
+        let points = self.points.read().unwrap();
+        let mut scored = points
+            .iter()
+            .filter(|(_, entry)| options.admits(&entry.payload))
+            .map(|(id, entry)| (dot(&query, &entry.vector), (id, entry)))
+            .collect::<Vec<_>>();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        options
+            .select(scored)
+            .into_iter()
+            .map(|(score, (id, entry))| {
+                let info = ImageInfo::from_payload(id.clone(), entry.payload.clone())?;
+                Ok(SearchHit::new(info, score))
+            })
+            .collect()
+    }
+
+    async fn scroll_with<T: DeserializeOwned + Send>(
+        &self,
+        offset: Option<&str>,
+        limit: usize,
+        filter: Option<&PayloadFilter>,
+    ) -> Result<ScrollPage<T>> {
+        let points = self.points.read().unwrap();
+        let range = match offset {
+            Some(offset) => points.range(offset.to_string()..),
+            None => points.range::<String, _>(..),
+        };
+        let mut range =
+            range.filter(|(_, entry)| filter.is_none_or(|filter| filter.matches(&entry.payload)));
+        let items = range
+            .by_ref()
+            .take(limit)
+            .map(|(id, entry)| ImageInfo::from_payload(id.clone(), entry.payload.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        let next_offset = range.next().map(|(id, _)| id.clone());
+        Ok(ScrollPage { items, next_offset })
+    }
+
+    async fn metadata(&self) -> Result<Option<CollectionMeta>> {
+        Ok(self.meta.read().unwrap().clone())
+    }
+
+    async fn set_metadata(&self, meta: &CollectionMeta) -> Result<()> {
+        *self.meta.write().unwrap() = Some(meta.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(id: &str) -> ImageInfo<String> {
+        ImageInfo::new(id, &format!("{id}.png"), Some(id.to_uppercase()))
+    }
+
+    async fn store() -> MemoryStore {
+        let store = MemoryStore::new(2);
+        store
+            .add(
+                &[vec![1.0, 0.0], vec![0.0, 1.0], vec![1.0, 1.0]],
+                &[info("a"), info("b"), info("c")],
+            )
+            .await
+            .unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn test_search_ranks_by_cosine() {
+        let store = store().await;
+        let hits = store.search::<String>(&[2.0, 0.1], 2).await.unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].id(), "a");
+        assert_eq!(hits[1].id(), "c");
+        assert_eq!(hits[0].extra().map(String::as_str), Some("A"));
+        assert!(hits[0].score() > hits[1].score());
+    }
+
+    #[tokio::test]
+    async fn test_search_with_offset_and_threshold() {
+        let store = store().await;
+        let options = SearchOptions::new(1).with_offset(1);
+        let hits = store
+            .search_with::<String>(&[2.0, 0.1], options)
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].id(), "c");
+
+        let options = SearchOptions::new(3).with_score_threshold(0.5);
+        let hits = store
+            .search_with::<String>(&[2.0, 0.1], options)
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().all(|hit| hit.score() >= 0.5));
+    }
+
+    #[tokio::test]
+    async fn test_filtered_search_scroll_and_delete() {
+        use crate::filter::Condition;
+
+        let store = store().await;
+        let not_a = PayloadFilter::new().must_not(Condition::matches("extra", "A"));
+        let options = SearchOptions::new(3).with_filter(not_a.clone());
+        let hits = store
+            .search_with::<String>(&[2.0, 0.1], options)
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].id(), "c");
+
+        let page = store
+            .scroll_with::<String>(None, 1, Some(&not_a))
+            .await
+            .unwrap();
+        assert_eq!(page.items[0].id(), "b");
+        assert_eq!(page.next_offset.as_deref(), Some("c"));
+
+        let only_b = PayloadFilter::new().must(Condition::any("extra", ["B"]));
+        store.delete_where(&only_b).await.unwrap();
+        let everything = PayloadFilter::new().must(PayloadFilter::new());
+        let err = store.delete_where(&everything).await.unwrap_err();
+        assert!(matches!(err, Error::DeletePointsError(_)));
+        assert_eq!(store.len(), 2);
+        assert!(store.get::<String>(&["b"]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delete_and_get() {
+        let store = store().await;
+        store.delete(&["a".to_string()]).await.unwrap();
+        let found = store.get::<String>(&["a", "b"]).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path(), "b.png");
+    }
+
+    #[tokio::test]
+    async fn test_scroll_pages() {
+        let store = store().await;
+        let first = store.scroll::<String>(None, 2).await.unwrap();
+        assert_eq!(first.items.len(), 2);
+        assert_eq!(first.next_offset.as_deref(), Some("c"));
+        let second = store
+            .scroll::<String>(first.next_offset.as_deref(), 2)
+            .await
+            .unwrap();
+        assert_eq!(second.items.len(), 1);
+        assert!(second.next_offset.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rejects_wrong_dimension() {
+        let store = MemoryStore::new(3);
+        let err = store.add(&[vec![1.0]], &[info("a")]).await.unwrap_err();
+        assert!(matches!(err, Error::UpsertPointsError(_)));
+    }
+}
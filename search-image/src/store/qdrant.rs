@@ -0,0 +1,914 @@
+use super::{ScrollPage, SearchOptions, VectorStore};
+use crate::{
+    app::{ImageInfo, SearchHit},
+    config::{CollectionParams, Compression, DbConfig, Layout, Metric, Quantization},
+    database,
+    error::{Error, Result},
+    filter::PayloadFilter,
+    schema::{CollectionMeta, Mismatch, SchemaMismatch},
+};
+use qdrant_client::{
+    Payload, Qdrant, QdrantBuilder,
+    config::CompressionEncoding,
+    qdrant::{
+        CollectionConfig, CompressionRatio, CreateAliasBuilder, CreateCollectionBuilder,
+        CreateFieldIndexCollectionBuilder, Distance, FieldType, GetPointsBuilder, HnswConfigDiff,
+        Memory, PointStruct, ProductQuantizationBuilder, ScalarQuantizationBuilder,
+        UpsertPointsBuilder, VectorParams, VectorParamsBuilder, quantization_config,
+        vectors_config,
+    },
+};
+use serde::{Serialize, de::DeserializeOwned};
+use std::collections::HashSet;
+
+/// [`VectorStore`] backed by a Qdrant collection.
+///
+/// With [`Layout::Alias`], the configured name is an alias over physical
+/// collections `<alias>_v<version>`, the generations. Reads and writes go
+/// through the alias, a new generation is filled next to the live one with
+/// [`QdrantStore::create_generation`] and [`App::migrate_from`], and
+/// [`QdrantStore::promote`] switches the alias over to it, or
+/// [`App::migrate_and_promote`] does both.
+///
+/// [`App::migrate_from`]: crate::App::migrate_from
+/// [`App::migrate_and_promote`]: crate::App::migrate_and_promote
+pub struct QdrantStore {
+    client: Qdrant,
+    collection: String,
+    params: CollectionParams,
+}
+
+impl QdrantStore {
+    pub fn new(client: Qdrant, collection: &str) -> Self {
+        Self {
+            client,
+            collection: collection.to_string(),
+            params: CollectionParams::default(),
+        }
+    }
+
+    /// Parameters new collections and generations are created with.
+    pub fn with_params(mut self, params: CollectionParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Connects to the server described by `db_config` and creates the
+    /// collection with `dim`-sized cosine vectors if it does not exist yet,
+    /// as a first generation behind the alias with [`Layout::Alias`].
+    /// An existing collection must already have vectors of that size and
+    /// distance, or [`Error::SchemaMismatch`] is returned.
+    pub async fn connect(db_config: &DbConfig, dim: usize) -> Result<Self> {
+        let client = QdrantBuilder::from_url(&db_config.qdrant_url())
+            .api_key(db_config.api_key()?)
+            .connect_timeout(db_config.connect_timeout())
+            .timeout(db_config.timeout())
+            .compression(Some(CompressionEncoding::Gzip))
+            .keep_alive_while_idle()
+            .build()
+            .map_err(|e| Error::QdrantBuildError(e.to_string()))?;
+
+        let store = Self::new(client, db_config.collection()).with_params(db_config.params());
+        let metric = store.params.distance();
+        let aliased = store.alias_target().await?.is_some();
+        let exists = aliased
+            || store
+                .client
+                .collection_exists(&store.collection)
+                .await
+                .map_err(|e| Error::CollectionError(e.to_string()))?;
+
+        match db_config.layout() {
+            _ if aliased => check_vectors(&store.client, &store.collection, dim, metric).await?,
+            Layout::Direct if exists => {
+                check_vectors(&store.client, &store.collection, dim, metric).await?
+            }
+            Layout::Direct => {
+                create_collection(&store.client, &store.collection, dim, &store.params).await?
+            }
+            Layout::Alias if exists => {
+                return Err(Error::CollectionError(format!(
+                    "`{}` is a collection, not an alias; migrate it into a generation first",
+                    store.collection
+                )));
+            }
+            Layout::Alias => {
+                let first = store.create_generation(dim).await?;
+                store.promote(first.version).await?;
+            }
+        }
+
+        Ok(store)
+    }
+
+    pub fn client(&self) -> &Qdrant {
+        &self.client
+    }
+
+    pub fn collection(&self) -> &str {
+        &self.collection
+    }
+
+    /// The collection [`QdrantStore::collection`] points at, `None` if it is
+    /// not an alias.
+    pub async fn alias_target(&self) -> Result<Option<String>> {
+        Ok(self
+            .aliases()
+            .await?
+            .into_iter()
+            .find(|(alias, _)| *alias == self.collection)
+            .map(|(_, collection)| collection))
+    }
+
+    /// Every alias on the server, with the collection it points at.
+    async fn aliases(&self) -> Result<Vec<(String, String)>> {
+        let response = self
+            .client
+            .list_aliases()
+            .await
+            .map_err(|e| Error::CollectionError(e.to_string()))?;
+        Ok(response
+            .aliases
+            .into_iter()
+            .map(|alias| (alias.alias_name, alias.collection_name))
+            .collect())
+    }
+
+    /// The physical collection behind [`QdrantStore::collection`], which may
+    /// be an alias.
+    pub async fn resolve(&self) -> Result<String> {
+        Ok(self
+            .alias_target()
+            .await?
+            .unwrap_or_else(|| self.collection.clone()))
+    }
+
+    /// Points `alias` at this store's physical collection, creating the alias
+    /// or moving it over from the collection it named before.
+    pub async fn point_alias(&self, alias: &str) -> Result<()> {
+        let collection = self.resolve().await?;
+        self.client
+            .create_alias(CreateAliasBuilder::new(collection, alias))
+            .await
+            .map_err(|e| Error::CollectionError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// The collection holding the [`CollectionMeta`] record as the payload of
+    /// a single point, so that searches and scrolls never see it. It follows
+    /// the physical collection, so an alias reports the record of whatever
+    /// it currently points at.
+    pub async fn meta_collection(&self) -> Result<String> {
+        Ok(format!("{}__meta", self.resolve().await?))
+    }
+
+    /// Where the collection differs from the parameters it would be created
+    /// with now. Unset parameters are not compared. Qdrant fixes most of
+    /// them at creation, so a drifted collection is best rebuilt as a new
+    /// generation.
+    pub async fn drift(&self) -> Result<Vec<Mismatch>> {
+        let (config, vectors) = collection_config(&self.client, &self.collection).await?;
+        Ok(drift(&self.params, &config, &vectors))
+    }
+
+    /// The physical collections behind the alias, oldest first.
+    pub async fn generations(&self) -> Result<Vec<Generation>> {
+        let live = self.alias_target().await?;
+        let response = self
+            .client
+            .list_collections()
+            .await
+            .map_err(|e| Error::CollectionError(e.to_string()))?;
+        let names = response.collections.into_iter().map(|c| c.name);
+        Ok(generations(&self.collection, live.as_deref(), names))
+    }
+
+    /// Creates an empty generation after the newest one, for `dim`-sized
+    /// vectors. The alias keeps pointing at the live generation until
+    /// [`QdrantStore::promote`].
+    pub async fn create_generation(&self, dim: usize) -> Result<Generation> {
+        let version = self
+            .generations()
+            .await?
+            .last()
+            .map_or(1, |generation| generation.version + 1);
+        let collection = format!("{}_v{version}", self.collection);
+        create_collection(&self.client, &collection, dim, &self.params).await?;
+        Ok(Generation {
+            version,
+            collection,
+            live: false,
+        })
+    }
+
+    /// A store reading and writing `generation` directly, bypassing the alias.
+    pub fn generation_store(&self, generation: &Generation) -> Self {
+        Self::new(self.client.clone(), &generation.collection).with_params(self.params)
+    }
+
+    /// Points the alias at generation `version`.
+    pub async fn promote(&self, version: u32) -> Result<()> {
+        let generation = self.generation(version).await?;
+        self.generation_store(&generation)
+            .point_alias(&self.collection)
+            .await
+    }
+
+    /// Points the alias back at the newest generation older than the live
+    /// one, and returns it.
+    pub async fn rollback(&self) -> Result<Generation> {
+        let generations = self.generations().await?;
+        let live = generations
+            .iter()
+            .position(|generation| generation.live)
+            .ok_or_else(|| self.no_generation("live"))?;
+        let previous = live
+            .checked_sub(1)
+            .map(|index| generations[index].clone())
+            .ok_or_else(|| self.no_generation("older"))?;
+        self.promote(previous.version).await?;
+        Ok(Generation {
+            live: true,
+            ..previous
+        })
+    }
+
+    /// Deletes the generations older than the live one, except the `keep`
+    /// newest of them, which remain available to [`QdrantStore::rollback`].
+    /// Generations newer than the live one may still be filling and are
+    /// never touched, nor are those another alias points at. Returns the
+    /// deleted collections.
+    pub async fn gc(&self, keep: usize) -> Result<Vec<String>> {
+        let generations = self.generations().await?;
+        let aliased = self
+            .aliases()
+            .await?
+            .into_iter()
+            .map(|(_, collection)| collection)
+            .collect::<HashSet<_>>();
+        let expired =
+            expired(&generations, keep, &aliased).ok_or_else(|| self.no_generation("live"))?;
+        let mut deleted = Vec::new();
+        for generation in expired {
+            for collection in [
+                format!("{}__meta", generation.collection),
+                generation.collection.clone(),
+            ] {
+                if self
+                    .client
+                    .collection_exists(&collection)
+                    .await
+                    .map_err(|e| Error::CollectionError(e.to_string()))?
+                {
+                    self.client
+                        .delete_collection(&collection)
+                        .await
+                        .map_err(|e| Error::CollectionError(e.to_string()))?;
+                }
+            }
+            deleted.push(generation.collection.clone());
+        }
+        Ok(deleted)
+    }
+
+    async fn generation(&self, version: u32) -> Result<Generation> {
+        self.generations()
+            .await?
+            .into_iter()
+            .find(|generation| generation.version == version)
+            .ok_or_else(|| self.no_generation(&format!("v{version}")))
+    }
+
+    fn no_generation(&self, which: &str) -> Error {
+        Error::CollectionError(format!(
+            "alias `{}` has no {which} generation",
+            self.collection
+        ))
+    }
+}
+
+/// One physical collection behind an alias, see [`QdrantStore::generations`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Generation {
+    pub version: u32,
+    pub collection: String,
+    /// Whether the alias currently points at it.
+    pub live: bool,
+}
+
+/// The generations of `alias` among the collections `names`, oldest first.
+/// Only names spelling out a version exactly, `<alias>_v<version>`, count.
+fn generations(
+    alias: &str,
+    live: Option<&str>,
+    names: impl IntoIterator<Item = String>,
+) -> Vec<Generation> {
+    let prefix = format!("{alias}_v");
+    let mut generations = names
+        .into_iter()
+        .filter_map(|name| {
+            let digits = name.strip_prefix(&prefix)?;
+            let version = digits.parse::<u32>().ok()?;
+            (version.to_string() == digits).then(|| Generation {
+                version,
+                live: live == Some(name.as_str()),
+                collection: name,
+            })
+        })
+        .collect::<Vec<_>>();
+    generations.sort_by_key(|generation| generation.version);
+    generations
+}
+
+/// The generations [`QdrantStore::gc`] deletes: those older than the live
+/// one beyond the `keep` newest of them, leaving out any collection in
+/// `aliased`. `None` if no generation is live.
+fn expired<'a>(
+    generations: &'a [Generation],
+    keep: usize,
+    aliased: &HashSet<String>,
+) -> Option<Vec<&'a Generation>> {
+    let live = generations.iter().position(|generation| generation.live)?;
+    Some(
+        generations[..live.saturating_sub(keep)]
+            .iter()
+            .filter(|generation| !aliased.contains(&generation.collection))
+            .collect(),
+    )
+}
+
+/// Where `config` and its `vectors` differ from `params`, see
+/// [`QdrantStore::drift`].
+fn drift(
+    params: &CollectionParams,
+    config: &CollectionConfig,
+    vectors: &VectorParams,
+) -> Vec<Mismatch> {
+    let mut drift = Vec::new();
+    let mut check = |field, expected: String, found: String| {
+        if expected != found {
+            drift.push(Mismatch {
+                field,
+                expected,
+                found,
+            });
+        }
+    };
+
+    check(
+        "distance",
+        format!("{:?}", distance(params.distance())),
+        distance_name(vectors.distance),
+    );
+    if let Some(on_disk) = params.on_disk() {
+        check(
+            "on_disk",
+            memory_name(Some(vector_memory(on_disk) as i32)),
+            memory_name(vectors.memory),
+        );
+    }
+    // A vector's own HNSW settings override the collection ones.
+    let hnsw = |field: fn(&HnswConfigDiff) -> Option<u64>| {
+        vectors
+            .hnsw_config
+            .as_ref()
+            .and_then(field)
+            .or(config.hnsw_config.as_ref().and_then(field))
+            .map_or_else(|| "unset".to_string(), |value| value.to_string())
+    };
+    if let Some(m) = params.hnsw_m() {
+        check("hnsw_m", m.to_string(), hnsw(|config| config.m));
+    }
+    if let Some(ef_construct) = params.hnsw_ef_construct() {
+        check(
+            "hnsw_ef_construct",
+            ef_construct.to_string(),
+            hnsw(|config| config.ef_construct),
+        );
+    }
+    if let Some(quantization) = params.quantization() {
+        let found = vectors
+            .quantization_config
+            .or(config.quantization_config)
+            .and_then(|quantization| quantization.quantization);
+        check(
+            "quantization",
+            describe_quantization(Some(&to_quantization(quantization))),
+            describe_quantization(found.as_ref()),
+        );
+    }
+    let collection = config.params.clone().unwrap_or_default();
+    if let Some(shard_number) = params.shard_number() {
+        check(
+            "shard_number",
+            shard_number.to_string(),
+            collection.shard_number.to_string(),
+        );
+    }
+    if let Some(replication_factor) = params.replication_factor() {
+        check(
+            "replication_factor",
+            replication_factor.to_string(),
+            collection.replication_factor.unwrap_or(1).to_string(),
+        );
+    }
+    drift
+}
+
+/// Payload fields filtered on as keywords, indexed in every collection this
+/// store creates. Qdrant scans the payload of every point for a filter on an
+/// unindexed field, and refuses it in strict mode.
+const KEYWORD_FIELDS: &[&str] = &["dirs", "preprocess"];
+
+async fn create_collection(
+    client: &Qdrant,
+    collection: &str,
+    dim: usize,
+    params: &CollectionParams,
+) -> Result<()> {
+    let mut vectors = VectorParamsBuilder::new(dim as u64, distance(params.distance()));
+    if let Some(on_disk) = params.on_disk() {
+        vectors = vectors.memory(vector_memory(on_disk));
+    }
+    let mut builder = CreateCollectionBuilder::new(collection).vectors_config(vectors);
+    if params.hnsw_m().is_some() || params.hnsw_ef_construct().is_some() {
+        builder = builder.hnsw_config(HnswConfigDiff {
+            m: params.hnsw_m(),
+            ef_construct: params.hnsw_ef_construct(),
+            ..Default::default()
+        });
+    }
+    if let Some(quantization) = params.quantization() {
+        builder = builder.quantization_config(to_quantization(quantization));
+    }
+    if let Some(shard_number) = params.shard_number() {
+        builder = builder.shard_number(shard_number);
+    }
+    if let Some(replication_factor) = params.replication_factor() {
+        builder = builder.replication_factor(replication_factor);
+    }
+    client
+        .create_collection(builder)
+        .await
+        .map_err(|e| Error::CollectionError(e.to_string()))?;
+    for field in KEYWORD_FIELDS {
+        client
+            .create_field_index(
+                CreateFieldIndexCollectionBuilder::new(collection, *field, FieldType::Keyword)
+                    .wait(true),
+            )
+            .await
+            .map_err(|e| Error::CollectionError(e.to_string()))?;
+    }
+    Ok(())
+}
+
+fn distance(metric: Metric) -> Distance {
+    match metric {
+        Metric::Cosine => Distance::Cosine,
+        Metric::Dot => Distance::Dot,
+        Metric::Euclid => Distance::Euclid,
+        Metric::Manhattan => Distance::Manhattan,
+    }
+}
+
+fn to_quantization(quantization: Quantization) -> quantization_config::Quantization {
+    match quantization {
+        Quantization::Scalar {
+            quantile,
+            always_ram,
+        } => {
+            let mut builder = ScalarQuantizationBuilder::default();
+            if let Some(quantile) = quantile {
+                builder = builder.quantile(quantile);
+            }
+            if always_ram {
+                builder = builder.memory(Memory::Pinned);
+            }
+            builder.into()
+        }
+        Quantization::Product {
+            compression,
+            always_ram,
+        } => {
+            let mut builder =
+                ProductQuantizationBuilder::new(compression_ratio(compression) as i32);
+            if always_ram {
+                builder = builder.memory(Memory::Pinned);
+            }
+            builder.into()
+        }
+    }
+}
+
+/// Where Qdrant keeps the original vectors. Dense vectors cannot be
+/// pinned, so RAM means a preloaded cache.
+fn vector_memory(on_disk: bool) -> Memory {
+    if on_disk {
+        Memory::Cold
+    } else {
+        Memory::Cached
+    }
+}
+
+fn memory_name(memory: Option<i32>) -> String {
+    match memory {
+        None => "unset".to_string(),
+        Some(memory) => Memory::try_from(memory)
+            .map(|memory| format!("{memory:?}"))
+            .unwrap_or_else(|_| memory.to_string()),
+    }
+}
+
+fn compression_ratio(compression: Compression) -> CompressionRatio {
+    match compression {
+        Compression::X4 => CompressionRatio::X4,
+        Compression::X8 => CompressionRatio::X8,
+        Compression::X16 => CompressionRatio::X16,
+        Compression::X32 => CompressionRatio::X32,
+        Compression::X64 => CompressionRatio::X64,
+    }
+}
+
+fn distance_name(distance: i32) -> String {
+    Distance::try_from(distance)
+        .map(|distance| format!("{distance:?}"))
+        .unwrap_or_else(|_| distance.to_string())
+}
+
+/// Same format for configured and stored quantization, so they can be
+/// compared as text.
+fn describe_quantization(quantization: Option<&quantization_config::Quantization>) -> String {
+    match quantization {
+        None => "none".to_string(),
+        Some(quantization_config::Quantization::Scalar(scalar)) => format!(
+            "scalar(quantile={:?}, memory={})",
+            scalar.quantile,
+            memory_name(scalar.memory)
+        ),
+        Some(quantization_config::Quantization::Product(product)) => format!(
+            "product({}, memory={})",
+            CompressionRatio::try_from(product.compression)
+                .map(|ratio| format!("{ratio:?}"))
+                .unwrap_or_else(|_| product.compression.to_string()),
+            memory_name(product.memory)
+        ),
+        Some(_) => "other".to_string(),
+    }
+}
+
+/// The configuration of `collection` and the parameters of its vector.
+async fn collection_config(
+    client: &Qdrant,
+    collection: &str,
+) -> Result<(CollectionConfig, VectorParams)> {
+    let info = client
+        .collection_info(collection)
+        .await
+        .map_err(|e| Error::CollectionError(e.to_string()))?;
+    let config = info.result.and_then(|info| info.config).unwrap_or_default();
+    let vectors = config
+        .params
+        .as_ref()
+        .and_then(|params| params.vectors_config.as_ref())
+        .and_then(|vectors| vectors.config.as_ref());
+    let Some(vectors_config::Config::Params(params)) = vectors else {
+        return Err(Error::CollectionError(format!(
+            "collection `{collection}` does not have a single unnamed vector"
+        )));
+    };
+    let params = *params;
+    Ok((config, params))
+}
+
+/// Compares the vector parameters Qdrant fixed when `collection` was
+/// created with the ones the app needs.
+async fn check_vectors(
+    client: &Qdrant,
+    collection: &str,
+    dim: usize,
+    metric: Metric,
+) -> Result<()> {
+    let (_, params) = collection_config(client, collection).await?;
+    let mut mismatches = Vec::new();
+    if params.size != dim as u64 {
+        mismatches.push(Mismatch {
+            field: "dim",
+            expected: dim.to_string(),
+            found: params.size.to_string(),
+        });
+    }
+    if params.distance != distance(metric) as i32 {
+        mismatches.push(Mismatch {
+            field: "metric",
+            expected: format!("{metric:?}"),
+            found: distance_name(params.distance),
+        });
+    }
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::SchemaMismatch(SchemaMismatch {
+            collection: collection.to_string(),
+            mismatches,
+        }))
+    }
+}
+
+impl VectorStore for QdrantStore {
+    fn metric(&self) -> Metric {
+        self.params.distance()
+    }
+
+    async fn add<T: Serialize + Sync>(
+        &self,
+        data: &[Vec<f32>],
+        image_info: &[ImageInfo<T>],
+    ) -> Result<()> {
+        database::add(&self.client, &self.collection, data, image_info).await
+    }
+
+    async fn delete(&self, ids: &[String]) -> Result<()> {
+        database::delete_by_ids(&self.client, &self.collection, ids).await
+    }
+
+    async fn delete_where(&self, filter: &PayloadFilter) -> Result<()> {
+        filter.check_deletable()?;
+        database::delete_by_filter(&self.client, &self.collection, filter.to_qdrant()).await
+    }
+
+    async fn get<T: DeserializeOwned + Send>(&self, ids: &[&str]) -> Result<Vec<ImageInfo<T>>> {
+        database::get_image_info(&self.client, &self.collection, ids).await
+    }
+
+    async fn search_with<T: DeserializeOwned + Send>(
+        &self,
+        feature: &[f32],
+        options: SearchOptions,
+    ) -> Result<Vec<SearchHit<T>>> {
+        database::similarity_search_with(
+            &self.client,
+            &self.collection,
+            feature,
+            &options,
+            true,
+            false,
+        )
+        .await?
+        .into_iter()
+        .map(SearchHit::try_from)
+        .collect()
+    }
+
+    async fn scroll_with<T: DeserializeOwned + Send>(
+        &self,
+        offset: Option<&str>,
+        limit: usize,
+        filter: Option<&PayloadFilter>,
+    ) -> Result<ScrollPage<T>> {
+        let (points, next_offset) = database::scroll_filtered(
+            &self.client,
+            &self.collection,
+            offset,
+            limit,
+            filter.map(PayloadFilter::to_qdrant),
+            true,
+            false,
+        )
+        .await?;
+        let items = points
+            .into_iter()
+            .map(ImageInfo::try_from)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ScrollPage { items, next_offset })
+    }
+
+    async fn metadata(&self) -> Result<Option<CollectionMeta>> {
+        let meta_collection = self.meta_collection().await?;
+        if !self
+            .client
+            .collection_exists(&meta_collection)
+            .await
+            .map_err(|e| Error::CollectionError(e.to_string()))?
+        {
+            return Ok(None);
+        }
+        let response = self
+            .client
+            .get_points(GetPointsBuilder::new(&meta_collection, vec![0.into()]).with_payload(true))
+            .await
+            .map_err(|e| Error::CollectionError(e.to_string()))?;
+        response
+            .result
+            .into_iter()
+            .next()
+            .map(|point| {
+                serde_json::from_value(database::payload_to_json(point.payload))
+                    .map_err(|e| Error::PayloadError(format!("{meta_collection}: {e}")))
+            })
+            .transpose()
+    }
+
+    async fn set_metadata(&self, meta: &CollectionMeta) -> Result<()> {
+        let meta_collection = self.meta_collection().await?;
+        if !self
+            .client
+            .collection_exists(&meta_collection)
+            .await
+            .map_err(|e| Error::CollectionError(e.to_string()))?
+        {
+            create_collection(
+                &self.client,
+                &meta_collection,
+                1,
+                &CollectionParams::default(),
+            )
+            .await?;
+        }
+        let payload = Payload::try_from(serde_json::to_value(meta)?)
+            .map_err(|e| Error::JsonToPayloadError(e.to_string()))?;
+        self.client
+            .upsert_points(
+                UpsertPointsBuilder::new(
+                    &meta_collection,
+                    vec![PointStruct::new(0, vec![1.0], payload)],
+                )
+                .wait(true),
+            )
+            .await
+            .map_err(|e| Error::UpsertPointsError(e.to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qdrant_client::qdrant::{self, QuantizationConfig, ScalarQuantization};
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_generations_parse_exact_names() {
+        let found = generations(
+            "images",
+            Some("images_v2"),
+            names(&[
+                "images_v10",
+                "images_v2",
+                "images_v1__meta",
+                "images_v01",
+                "images_v+3",
+                "images_vx",
+                "other_v1",
+                "images_v1",
+            ]),
+        );
+        let versions = found.iter().map(|g| g.version).collect::<Vec<_>>();
+        assert_eq!(versions, [1, 2, 10]);
+        assert!(found[1].live && !found[0].live && !found[2].live);
+        assert_eq!(found[2].collection, "images_v10");
+    }
+
+    #[test]
+    fn test_expired_keeps_recent_and_aliased_generations() {
+        let all = generations(
+            "images",
+            Some("images_v5"),
+            names(&[
+                "images_v1",
+                "images_v2",
+                "images_v3",
+                "images_v4",
+                "images_v5",
+                "images_v6",
+            ]),
+        );
+        let collections = |expired: Vec<&Generation>| {
+            expired
+                .into_iter()
+                .map(|g| g.collection.clone())
+                .collect::<Vec<_>>()
+        };
+        let none = HashSet::new();
+        assert_eq!(
+            collections(expired(&all, 1, &none).unwrap()),
+            ["images_v1", "images_v2", "images_v3"]
+        );
+        assert!(expired(&all, 4, &none).unwrap().is_empty());
+        assert!(expired(&all, 10, &none).unwrap().is_empty());
+
+        // Another alias still serving v2 keeps it alive.
+        let aliased = HashSet::from(["images_v2".to_string(), "images_v5".to_string()]);
+        assert_eq!(
+            collections(expired(&all, 0, &aliased).unwrap()),
+            ["images_v1", "images_v3", "images_v4"]
+        );
+
+        let unpromoted = generations("images", None, names(&["images_v1", "images_v2"]));
+        assert!(expired(&unpromoted, 0, &none).is_none());
+    }
+
+    #[test]
+    fn test_generation_store_keeps_params() {
+        let client = Qdrant::from_url("http://localhost:6334").build().unwrap();
+        let params = CollectionParams::default().with_distance(Metric::Dot);
+        let store = QdrantStore::new(client, "images").with_params(params);
+        let generation = store.generation_store(&Generation {
+            version: 2,
+            collection: "images_v2".to_string(),
+            live: false,
+        });
+        assert_eq!(generation.collection(), "images_v2");
+        assert_eq!(generation.metric(), store.metric());
+        assert_eq!(generation.metric(), Metric::Dot);
+    }
+
+    #[test]
+    fn test_to_quantization() {
+        let scalar = to_quantization(Quantization::Scalar {
+            quantile: Some(0.99),
+            always_ram: true,
+        });
+        let quantization_config::Quantization::Scalar(ScalarQuantization {
+            quantile, memory, ..
+        }) = &scalar
+        else {
+            panic!("expected scalar quantization, got {scalar:?}");
+        };
+        assert_eq!(*quantile, Some(0.99));
+        assert_eq!(*memory, Some(Memory::Pinned as i32));
+        assert_eq!(
+            describe_quantization(Some(&scalar)),
+            "scalar(quantile=Some(0.99), memory=Pinned)"
+        );
+
+        let product = to_quantization(Quantization::Product {
+            compression: Compression::X32,
+            always_ram: false,
+        });
+        assert_eq!(
+            describe_quantization(Some(&product)),
+            "product(X32, memory=unset)"
+        );
+        assert_eq!(describe_quantization(None), "none");
+    }
+
+    #[test]
+    fn test_drift_compares_set_parameters_only() {
+        let vectors = VectorParamsBuilder::new(4, Distance::Cosine)
+            .memory(Memory::Cold)
+            .build();
+        let config = CollectionConfig {
+            hnsw_config: Some(HnswConfigDiff {
+                m: Some(16),
+                ..Default::default()
+            }),
+            quantization_config: Some(QuantizationConfig {
+                quantization: Some(to_quantization(Quantization::Scalar {
+                    quantile: None,
+                    always_ram: false,
+                })),
+            }),
+            params: Some(qdrant::CollectionParams {
+                shard_number: 2,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        // Nothing set beyond the default distance, nothing to report.
+        assert!(drift(&CollectionParams::default(), &config, &vectors).is_empty());
+
+        let params = CollectionParams::default()
+            .with_on_disk(true)
+            .with_hnsw(32, 100)
+            .with_shards(2, 1);
+        let fields = drift(&params, &config, &vectors)
+            .into_iter()
+            .map(|mismatch| (mismatch.field, mismatch.expected, mismatch.found))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            [
+                ("hnsw_m", "32".to_string(), "16".to_string()),
+                ("hnsw_ef_construct", "100".to_string(), "unset".to_string()),
+            ]
+        );
+
+        let params = CollectionParams::default()
+            .with_distance(Metric::Dot)
+            .with_on_disk(false)
+            .with_quantization(Quantization::Product {
+                compression: Compression::X16,
+                always_ram: false,
+            });
+        let fields = drift(&params, &config, &vectors)
+            .into_iter()
+            .map(|mismatch| mismatch.field)
+            .collect::<Vec<_>>();
+        assert_eq!(fields, ["distance", "on_disk", "quantization"]);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::{
+    error::{Error, Result},
+    ingest::{DuplicateMatch, Skipped},
+    utils::content_hash,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, io::Write, path::Path, time::UNIX_EPOCH};
+
+/// What [`App::sync_folder`](crate::App::sync_folder) knows about one
+/// indexed file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub id: String,
+    pub size: u64,
+    /// Modification time in nanoseconds since the Unix epoch.
+    pub mtime: u64,
+    /// Hex-encoded SHA-256 of the file content.
+    pub hash: String,
+    /// The file was left out as a duplicate of point `id`, which it does not
+    /// own.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub duplicate: bool,
+}
+
+/// Files indexed from a folder, keyed by path, persisted as JSON between
+/// syncs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    entries: BTreeMap<String, ManifestEntry>,
+}
+
+impl Manifest {
+    /// Reads the manifest at `path`, or starts an empty one if there is none.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        match std::fs::read(path.as_ref()) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| Error::ManifestError(format!("{}: {e}", path.as_ref().display()))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes the manifest through a temporary file so a crash never leaves
+    /// a half-written one behind.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(&serde_json::to_vec_pretty(self)?)?;
+        // Flush to disk before the rename, or a crash could leave an empty
+        // manifest in place of the old one.
+        file.sync_all()?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    pub fn get(&self, path: &str) -> Option<&ManifestEntry> {
+        self.entries.get(path)
+    }
+
+    pub fn insert(&mut self, path: String, entry: ManifestEntry) {
+        self.entries.insert(path, entry);
+    }
+
+    pub fn remove(&mut self, path: &str) -> Option<ManifestEntry> {
+        self.entries.remove(path)
+    }
+
+    /// Whether a stored file still owns point `id`. When only files that
+    /// duplicate it remain, the first of them takes it over, so the point
+    /// lives as long as any of them.
+    pub fn claim(&mut self, id: &str) -> bool {
+        let mut entries = self.entries.values_mut().filter(|entry| entry.id == id);
+        let Some(first) = entries.next() else {
+            return false;
+        };
+        if first.duplicate && !entries.any(|entry| !entry.duplicate) {
+            first.duplicate = false;
+        }
+        true
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Size and modification time, the cheap part of change detection.
+pub(crate) fn file_stamp(path: &Path) -> Result<(u64, u64)> {
+    let metadata = std::fs::metadata(path)?;
+    let mtime = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default();
+    Ok((metadata.len(), mtime))
+}
+
+/// How a scanned file relates to the manifest.
+pub(crate) enum Change {
+    Unchanged,
+    /// Same content, only the stamp moved; the manifest needs refreshing.
+    Touched(ManifestEntry),
+    Added {
+        size: u64,
+        mtime: u64,
+        hash: String,
+    },
+    Modified {
+        id: String,
+        size: u64,
+        mtime: u64,
+        hash: String,
+    },
+}
+
+/// Compares `path` with its manifest entry, hashing only when size or
+/// modification time differ.
+pub(crate) fn detect_change(manifest: &Manifest, path: &Path) -> Result<Change> {
+    let (size, mtime) = file_stamp(path)?;
+    let previous = manifest.get(&manifest_key(path));
+    if previous.is_some_and(|entry| entry.size == size && entry.mtime == mtime) {
+        return Ok(Change::Unchanged);
+    }
+    let hash = content_hash(path)?;
+    Ok(match previous {
+        Some(entry) if entry.hash == hash => Change::Touched(ManifestEntry {
+            size,
+            mtime,
+            ..entry.clone()
+        }),
+        // A file left out as a duplicate owns no point to replace.
+        Some(entry) if !entry.duplicate => Change::Modified {
+            id: entry.id.clone(),
+            size,
+            mtime,
+            hash,
+        },
+        _ => Change::Added { size, mtime, hash },
+    })
+}
+
+/// Outcome of [`App::sync_folder`](crate::App::sync_folder).
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+    pub skipped: Vec<Skipped>,
+    pub duplicates: Vec<DuplicateMatch>,
+}
+
+pub(crate) fn manifest_key(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::TempDir;
+
+    #[test]
+    fn test_detect_change() {
+        let dir = TempDir::new("sync");
+        let file = dir.join("a.png");
+        std::fs::write(&file, b"one").unwrap();
+
+        let mut manifest = Manifest::default();
+        let Change::Added { size, mtime, hash } = detect_change(&manifest, &file).unwrap() else {
+            panic!("expected a new file");
+        };
+        manifest.insert(
+            manifest_key(&file),
+            ManifestEntry {
+                id: "1".to_string(),
+                size,
+                mtime,
+                hash,
+                duplicate: false,
+            },
+        );
+        assert!(matches!(
+            detect_change(&manifest, &file).unwrap(),
+            Change::Unchanged
+        ));
+
+        std::fs::write(&file, b"two").unwrap();
+        let mut entry = manifest.get(&manifest_key(&file)).unwrap().clone();
+        entry.mtime += 1;
+        manifest.insert(manifest_key(&file), entry);
+        assert!(matches!(
+            detect_change(&manifest, &file).unwrap(),
+            Change::Modified { id, .. } if id == "1"
+        ));
+
+        let path = dir.join("manifest.json");
+        manifest.save(&path).unwrap();
+        assert_eq!(Manifest::load(&path).unwrap().len(), 1);
+    }
+}
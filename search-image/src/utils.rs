@@ -1,25 +1,110 @@
 use crate::error::Result;
-use candle_core::{DType, Device, Tensor};
 use image::DynamicImage;
+use sha2::{Digest, Sha256};
+use std::io::Read;
 
 pub fn load_image(path: impl AsRef<std::path::Path>) -> Result<DynamicImage> {
     Ok(image::ImageReader::open(&path)?.decode()?)
 }
 
-pub fn image_to_tensor(
-    path: impl AsRef<std::path::Path>,
-    resize_shape: Option<(u32, u32)>,
-) -> Result<Tensor> {
-    let original_img = load_image(&path)?;
-    let img = match resize_shape {
-        Some((width, height)) => {
-            original_img.resize_to_fill(width, height, image::imageops::FilterType::Triangle)
+pub fn decode_image(bytes: &[u8]) -> Result<DynamicImage> {
+    Ok(image::load_from_memory(bytes)?)
+}
+
+/// Hex-encoded SHA-256 of the file at `path`, read in fixed-size blocks.
+pub fn content_hash(path: impl AsRef<std::path::Path>) -> Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Namespace of the UUIDv5 point ids derived from files.
+const ID_NAMESPACE: uuid::Uuid = uuid::Uuid::from_u128(0x6f1c_52a8_0d3e_4b7a_9c21_5e8f_a4d3_b710);
+
+/// Point id of a file whose content hashes to `hash`, see [`content_hash`].
+pub fn content_id(hash: &str) -> String {
+    uuid::Uuid::new_v5(&ID_NAMESPACE, format!("sha256:{hash}").as_bytes()).to_string()
+}
+
+/// Point id of the file at `path`. The path is made absolute and uses `/` as
+/// separator so the same file gets the same id however it was reached.
+pub fn path_id(path: impl AsRef<std::path::Path>) -> String {
+    let path = path.as_ref();
+    let absolute = std::fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    let normalized = absolute
+        .to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "/");
+    uuid::Uuid::new_v5(&ID_NAMESPACE, format!("path:{normalized}").as_bytes()).to_string()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+
+    /// A fresh directory under the system temp dir, deleted with its content
+    /// when dropped, so a failing test does not leave it behind.
+    pub(crate) struct TempDir(PathBuf);
+
+    impl TempDir {
+        pub(crate) fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("search-image-{name}-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl std::ops::Deref for TempDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl AsRef<Path> for TempDir {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
         }
-        None => original_img,
-    };
-    let width = img.width() as usize;
-    let height = img.height() as usize;
-    let raw_data = img.into_rgb8().into_raw();
-    let data = Tensor::from_vec(raw_data, (width, height, 3), &Device::Cpu)?.permute((2, 0, 1))?;
-    Ok((data.to_dtype(DType::F32)? / 255.0)?)
+    }
+
+    #[test]
+    fn test_derived_ids() {
+        let dir = TempDir::new("ids");
+        std::fs::create_dir_all(dir.join("copy")).unwrap();
+        std::fs::write(dir.join("a.png"), b"same").unwrap();
+        std::fs::write(dir.join("copy/a.png"), b"same").unwrap();
+        std::fs::write(dir.join("b.png"), b"other").unwrap();
+
+        let hash = |name: &str| content_id(&content_hash(dir.join(name)).unwrap());
+        assert_eq!(hash("a.png"), hash("copy/a.png"));
+        assert_ne!(hash("a.png"), hash("b.png"));
+
+        assert_eq!(
+            path_id(dir.join("a.png")),
+            path_id(dir.join("copy/../a.png"))
+        );
+        assert_ne!(path_id(dir.join("a.png")), path_id(dir.join("copy/a.png")));
+    }
 }
@@ -0,0 +1,334 @@
+use crate::{
+    App,
+    error::{Error, Result},
+    scan::FolderScan,
+    store::VectorStore,
+    sync::SyncReport,
+    utils::path_id,
+};
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{sync::mpsc, time::Instant};
+
+/// Folders kept in sync by [`App::watch`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WatchConfig {
+    roots: Vec<PathBuf>,
+    /// Quiet period after the last event before a batch is indexed.
+    debounce_ms: u64,
+    /// Wait before a root whose sync failed is synced again.
+    retry_ms: u64,
+    /// Directory holding one sync manifest per root.
+    state_dir: PathBuf,
+    scan: FolderScan,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            roots: Vec::new(),
+            debounce_ms: 500,
+            retry_ms: 5_000,
+            state_dir: PathBuf::from("./.index/watch"),
+            scan: FolderScan::default(),
+        }
+    }
+}
+
+impl WatchConfig {
+    pub fn new<P: AsRef<Path>>(roots: &[P]) -> Self {
+        Self {
+            roots: roots
+                .iter()
+                .map(|root| root.as_ref().to_path_buf())
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce_ms = debounce.as_millis() as u64;
+        self
+    }
+
+    pub fn with_retry_backoff(mut self, backoff: Duration) -> Self {
+        self.retry_ms = backoff.as_millis() as u64;
+        self
+    }
+
+    pub fn with_state_dir(mut self, state_dir: impl AsRef<Path>) -> Self {
+        self.state_dir = state_dir.as_ref().to_path_buf();
+        self
+    }
+
+    pub fn with_scan(mut self, scan: FolderScan) -> Self {
+        self.scan = scan;
+        self
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    pub fn debounce(&self) -> Duration {
+        Duration::from_millis(self.debounce_ms)
+    }
+
+    pub fn retry_backoff(&self) -> Duration {
+        Duration::from_millis(self.retry_ms)
+    }
+
+    pub fn state_dir(&self) -> &Path {
+        &self.state_dir
+    }
+
+    pub fn scan(&self) -> &FolderScan {
+        &self.scan
+    }
+
+    /// Manifest file tracking what has been indexed from `root`.
+    pub fn manifest_path(&self, root: impl AsRef<Path>) -> PathBuf {
+        self.state_dir.join(format!("{}.json", path_id(root)))
+    }
+}
+
+/// Paths touched since the last flush, per root.
+struct Pending {
+    paths: Vec<BTreeSet<PathBuf>>,
+    /// Roots whose events were lost, resynced with a full scan.
+    rescan: HashSet<usize>,
+}
+
+impl<S: VectorStore> App<S> {
+    /// Keeps the collection in sync with the roots of `config` until
+    /// `shutdown` completes.
+    ///
+    /// Every root is watched, then reconciled with
+    /// [`sync_folder`](Self::sync_folder); events raised during that first
+    /// sync are kept for the next batch. Filesystem events are collected
+    /// until no new one arrived for the debounce period, and the touched
+    /// paths are applied in one [`sync_paths`](Self::sync_paths) call per
+    /// root, so extraction runs in batches. `on_sync` receives the outcome
+    /// of every sync. A failed sync does not stop the watcher: its root is
+    /// scanned in full at the next flush, which comes after the retry
+    /// backoff at the latest.
+    ///
+    /// Roots are made canonical first, since filesystem events always carry
+    /// absolute paths; `on_sync` and the manifests see them in that form.
+    pub async fn watch(
+        &self,
+        config: &WatchConfig,
+        shutdown: impl Future<Output = ()>,
+        mut on_sync: impl FnMut(&Path, Result<SyncReport>),
+    ) -> Result<()> {
+        std::fs::create_dir_all(config.state_dir())?;
+        let roots = config
+            .roots()
+            .iter()
+            .map(|root| {
+                std::fs::canonicalize(root)
+                    .map_err(|e| Error::WatchError(format!("{}: {e}", root.display())))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut pending = Pending {
+            paths: vec![BTreeSet::new(); roots.len()],
+            rescan: HashSet::new(),
+        };
+        // Registered before the initial sync, so that changes made while it
+        // runs are buffered in the channel and applied afterwards.
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            // The receiver only goes away once `watch` returns.
+            let _ = tx.send(event);
+        })
+        .map_err(|e| Error::WatchError(e.to_string()))?;
+        for root in &roots {
+            watcher
+                .watch(root, RecursiveMode::Recursive)
+                .map_err(|e| Error::WatchError(format!("{}: {e}", root.display())))?;
+        }
+
+        for (index, root) in roots.iter().enumerate() {
+            let result = self
+                .sync_folder(root, config.scan(), config.manifest_path(root))
+                .await;
+            if result.is_err() {
+                pending.rescan.insert(index);
+            }
+            on_sync(root, result);
+        }
+
+        let retry = || Some(Instant::now() + config.retry_backoff());
+        let mut deadline = if pending.rescan.is_empty() {
+            None
+        } else {
+            retry()
+        };
+        tokio::pin!(shutdown);
+        loop {
+            let flush = async {
+                match deadline {
+                    Some(deadline) => tokio::time::sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                _ = &mut shutdown => return Ok(()),
+                event = rx.recv() => {
+                    let Some(event) = event else {
+                        return Err(Error::WatchError("watcher stopped".to_string()));
+                    };
+                    match event {
+                        Ok(event) if matches!(event.kind, EventKind::Access(_)) => continue,
+                        Ok(event) if event.need_rescan() => {
+                            pending.rescan.extend(0..roots.len());
+                        }
+                        Ok(event) => {
+                            for path in event.paths {
+                                let root = roots.iter().position(|root| path.starts_with(root));
+                                if let Some(index) = root {
+                                    pending.paths[index].insert(path);
+                                }
+                            }
+                        }
+                        Err(_) => pending.rescan.extend(0..roots.len()),
+                    }
+                    deadline = Some(Instant::now() + config.debounce());
+                }
+                _ = flush => {
+                    deadline = None;
+                    for (index, root) in roots.iter().enumerate() {
+                        let paths = std::mem::take(&mut pending.paths[index]);
+                        let manifest = config.manifest_path(root);
+                        let result = if pending.rescan.remove(&index) {
+                            self.sync_folder(root, config.scan(), manifest).await
+                        } else if !paths.is_empty() {
+                            let paths = paths.into_iter().collect::<Vec<_>>();
+                            self.sync_paths(root, config.scan(), &paths, manifest).await
+                        } else {
+                            continue;
+                        };
+                        if result.is_err() {
+                            pending.rescan.insert(index);
+                        }
+                        on_sync(root, result);
+                    }
+                    if !pending.rescan.is_empty() {
+                        deadline = retry();
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        app::tests::{FailingStore, images},
+        extractor::Extractor,
+        model::tests::MeanColor,
+        store::MemoryStore,
+        utils::tests::TempDir,
+    };
+    use candle_core::Device;
+    use std::{path::Component, sync::Arc};
+    use tokio::sync::Notify;
+
+    #[tokio::test]
+    async fn test_failed_sync_is_retried_without_events() {
+        let (dir, _) = images();
+        let state_dir = TempDir::new("watch");
+        let config = WatchConfig::new(&[&dir])
+            .with_retry_backoff(Duration::from_millis(50))
+            .with_state_dir(&state_dir);
+        let store = FailingStore {
+            inner: MemoryStore::new(2),
+            adds: Default::default(),
+            fail_at: 0,
+        };
+        let app = App::with_store(
+            store,
+            Extractor::from_model(Arc::new(MeanColor), &Device::Cpu),
+        );
+
+        // The store fails the initial sync, nothing touches the folder after.
+        let synced = Notify::new();
+        let mut results = Vec::new();
+        let watch = app.watch(&config, synced.notified(), |_, result| {
+            if result.is_ok() {
+                synced.notify_one();
+            }
+            results.push(result);
+        });
+        tokio::time::timeout(Duration::from_secs(10), watch)
+            .await
+            .expect("the failed sync was not retried")
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(matches!(results[0], Err(Error::UpsertPointsError(_))));
+        assert_eq!(results[1].as_ref().unwrap().added, 3);
+        assert_eq!(app.store().inner.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_relative_root_receives_events() {
+        let (dir, paths) = images();
+        let state_dir = TempDir::new("watch");
+        // `dir` as seen from the working directory.
+        let normal = |path: &Path| {
+            path.components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .count()
+        };
+        let cwd = std::env::current_dir().unwrap();
+        let relative = std::iter::repeat_n(Path::new(".."), normal(&cwd))
+            .collect::<PathBuf>()
+            .join(
+                dir.components()
+                    .filter(|c| matches!(c, Component::Normal(_)))
+                    .collect::<PathBuf>(),
+            );
+        assert!(relative.is_relative());
+        let config = WatchConfig::new(&[&relative])
+            .with_debounce(Duration::from_millis(50))
+            .with_state_dir(&state_dir);
+        let app = App::with_store(
+            MemoryStore::new(2),
+            Extractor::from_model(Arc::new(MeanColor), &Device::Cpu),
+        );
+
+        // Once the initial sync is done, one file is added and one removed.
+        let synced = Notify::new();
+        let (mut added, mut removed) = (0, 0);
+        let mut syncs = 0;
+        let watch = app.watch(&config, synced.notified(), |root, result| {
+            assert!(root.is_absolute());
+            let report = result.unwrap();
+            syncs += 1;
+            if syncs == 1 {
+                assert_eq!(report.added, 3);
+                std::fs::copy(&paths[2], dir.join("d.png")).unwrap();
+                std::fs::remove_file(&paths[0]).unwrap();
+                return;
+            }
+            added += report.added;
+            removed += report.removed;
+            if (added, removed) == (1, 1) {
+                synced.notify_one();
+            }
+        });
+        tokio::time::timeout(Duration::from_secs(10), watch)
+            .await
+            .expect("the changes were not picked up")
+            .unwrap();
+        assert_eq!(app.store().len(), 3);
+    }
+}
@@ -1,9 +1,5 @@
 use crate::configration::Config;
-use search_image::{
-    App,
-    config::{Device, MobilenetConfig},
-    error::Error,
-};
+use search_image::{App, config::Device, error::Error};
 
 pub async fn get() -> App {
     let config = Config::load();
@@ -14,13 +10,16 @@ pub async fn get() -> App {
             Device::Cpu
         }
     };
-    let mobilenet_config = MobilenetConfig::new(config.mobilenet.kind(), device);
-    match App::new(&config.db, &mobilenet_config).await {
+    let mobilenet_config = config.mobilenet.clone().with_device(device);
+    let app = match App::new(&config.db, &mobilenet_config).await {
         Ok(app) => app,
         Err(e) => {
             match e {
                 Error::CUDAError => tracing::error!("Failed to use CUDA"),
                 Error::MetalError => tracing::error!("Failed to use Metal"),
+                Error::WeightsNotFound(e) => {
+                    tracing::error!("Failed to find model weights: {}", e)
+                }
                 Error::HuggingFaceApiError(e) => {
                     tracing::error!("Failed to use HuggingFace API: {}", e)
                 }
@@ -34,5 +33,22 @@ pub async fn get() -> App {
             }
             std::process::exit(1);
         }
+    };
+    if let Some(store) = app.store().as_qdrant() {
+        match store.drift().await {
+            Ok(drift) => {
+                for mismatch in drift {
+                    tracing::warn!(
+                        "Collection `{}` has {} = {}, config asks for {}",
+                        store.collection(),
+                        mismatch.field,
+                        mismatch.found,
+                        mismatch.expected
+                    );
+                }
+            }
+            Err(e) => tracing::warn!("Failed to check collection parameters: {}", e),
+        }
     }
+    app
 }